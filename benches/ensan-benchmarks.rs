@@ -1,4 +1,4 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use ensan::Engine;
 
 macro_rules! bench_group {
@@ -54,7 +54,50 @@ fn ref_attr_nblks_3_lbls(en: &mut Engine) {
     en.clean_up();
 }
 
+/// `n` attributes each referencing the previous one, both at the top level and in a block.
+fn chained_attrs(n: usize) -> String {
+    let mut hcl = String::from("a0 = 0\nblk {\n  b0 = 0\n");
+    for i in 1..n {
+        hcl += &format!("  b{i} = b{} + 1\n", i - 1);
+    }
+    hcl += "}\n";
+    for i in 1..n {
+        hcl += &format!("a{i} = a{} + 1\n", i - 1);
+    }
+    hcl
+}
+
+/// `n` blocks referencing the document and themselves through `root`, `parent` and `self`.
+fn builtin_refs(n: usize) -> String {
+    let mut hcl = String::from("z = 1\n");
+    for i in 0..n {
+        hcl += &format!("b{i} {{\n  y = root.z + parent.z\n  w = self.y\n}}\n");
+    }
+    hcl
+}
+
+// the time should grow linearly with `n`
+fn bench_scaling(c: &mut Criterion, name: &str, input: fn(usize) -> String) {
+    let mut en = Engine::new();
+    let mut group = c.benchmark_group(name);
+    for n in [500, 2000] {
+        let hcl = input(n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &hcl, |b, hcl| {
+            b.iter(|| {
+                let _ = en.parse(hcl).unwrap();
+                en.clean_up();
+            });
+        });
+    }
+    group.finish();
+}
+
+fn criterion_scaling(c: &mut Criterion) {
+    bench_scaling(c, "chained_attrs", chained_attrs);
+    bench_scaling(c, "builtin_refs", builtin_refs);
+}
+
 bench_group!(criterion_refs => ref_attr_in_blk ref_attr_in_10_blks ref_attr_nblks_3_lbls);
 
-criterion_group!(engine_benches, criterion_refs);
+criterion_group!(engine_benches, criterion_refs, criterion_scaling);
 criterion_main!(engine_benches);
@@ -1,16 +1,81 @@
 use proc_macro::TokenStream;
 use syn::{parse::Parse, punctuated::Punctuated, spanned::Spanned, Expr, Token};
 
+/// A parameter in `#[ensan_fn(...)]`
+enum Param {
+    /// `Type`
+    Required(Expr),
+    /// `opt Type = default` or `opt Type`
+    Optional(Expr, Option<Expr>),
+    /// `..Type`
+    Variadic(Expr),
+}
+
+impl Parse for Param {
+    fn parse(input: syn::parse::ParseStream) -> syn::parse::Result<Self> {
+        if input.peek(syn::Ident) && input.peek2(syn::Ident) {
+            let kw: syn::Ident = input.parse()?;
+            if kw != "opt" {
+                return Err(syn::Error::new(kw.span(), "expected `opt Type = default`"));
+            }
+            return Ok(match input.parse()? {
+                Expr::Assign(assign) => Self::Optional(*assign.left, Some(*assign.right)),
+                ty => Self::Optional(ty, None),
+            });
+        }
+        Ok(match input.parse()? {
+            Expr::Range(syn::ExprRange {
+                start: None,
+                limits: syn::RangeLimits::HalfOpen(_),
+                end: Some(ty),
+                ..
+            }) => Self::Variadic(*ty),
+            ty => Self::Required(ty),
+        })
+    }
+}
+
 struct EnsanFnAttrArgs {
     args: Vec<Expr>,
+    /// the types and default values of the optional parameters after `args`
+    optional: Vec<(Expr, Option<Expr>)>,
+    /// the type of the variadic parameter, written as `..Type` at the end
+    variadic: Option<Expr>,
 }
 
 impl Parse for EnsanFnAttrArgs {
     fn parse(input: syn::parse::ParseStream) -> syn::parse::Result<Self> {
-        let vars = Punctuated::<Expr, Token![,]>::parse_terminated(input)?;
-        Ok(Self {
-            args: vars.into_iter().collect(),
-        })
+        let params = Punctuated::<Param, Token![,]>::parse_terminated(input)?;
+        let mut out = Self {
+            args: vec![],
+            optional: vec![],
+            variadic: None,
+        };
+        for param in params {
+            let (ty, err) = match param {
+                Param::Required(ty) if out.optional.is_empty() && out.variadic.is_none() => {
+                    out.args.push(ty);
+                    continue;
+                }
+                Param::Optional(ty, default) if out.variadic.is_none() => {
+                    out.optional.push((ty, default));
+                    continue;
+                }
+                Param::Variadic(ty) if out.optional.is_empty() && out.variadic.is_none() => {
+                    out.variadic = Some(ty);
+                    continue;
+                }
+                Param::Required(ty) => (ty, "required parameters must come first"),
+                Param::Optional(ty, _) => (ty, "optional parameters cannot follow `..Type`"),
+                Param::Variadic(ty) => (
+                    ty,
+                    "only the last parameter can be variadic (`..Type`), and it cannot be \
+                     combined with optional parameters",
+                ),
+            };
+            return Err(syn::Error::new(ty.span(), err));
+        }
+        Ok(out)
     }
 }
 
@@ -29,7 +94,7 @@ fn mutate_tokens(args: &[&Expr]) -> proc_macro2::TokenStream {
     };
 
     if let Expr::Path(epath) = arg {
-        return quote::quote! { ::hcl::eval::ParamType::#epath };
+        return quote::quote! { ::ensan::hcl::eval::ParamType::#epath };
     }
     let Expr::Call(ecall) = arg else {
         return syn::Error::new(arg.span(), "not a call/path syntax").into_compile_error();
@@ -41,43 +106,305 @@ fn mutate_tokens(args: &[&Expr]) -> proc_macro2::TokenStream {
     let inner = mutate_tokens(&ecall.args.iter().collect::<Vec<_>>());
 
     quote::quote! {
-        ::hcl::eval::ParamType::#t(::std::boxed::Box::new(#inner))
+        ::ensan::hcl::eval::ParamType::#t(::std::boxed::Box::new(#inner))
     }
 }
 
-/// Save the FuncDef into a global variable "ENSAN_INTERNAL_FNS"
+/// Whether `#[ensan_fn]` is used on a function with a typed signature, i.e. the attribute has no
+/// parameter types and the function does not take `FuncArgs`.
+fn is_typed(attr: &syn::Attribute, f: &syn::ItemFn) -> bool {
+    let no_args = match &attr.meta {
+        syn::Meta::Path(_) => true,
+        syn::Meta::List(list) => list.tokens.is_empty(),
+        syn::Meta::NameValue(_) => false,
+    };
+    let takes_funcargs = match f.sig.inputs.iter().collect::<Vec<_>>()[..] {
+        [syn::FnArg::Typed(pt)] => matches!(&*pt.ty, syn::Type::Path(tp)
+            if tp.path.segments.last().is_some_and(|seg| seg.ident == "FuncArgs")),
+        _ => false,
+    };
+    no_args && !takes_funcargs
+}
+
+/// The element type `T` of `Vec<T>` or `Option<T>`, depending on `wrapper`.
+fn generic_elem<'t>(ty: &'t syn::Type, wrapper: &str) -> Option<&'t syn::Type> {
+    let syn::Type::Path(tp) = ty else { return None };
+    let seg = tp.path.segments.last().filter(|seg| seg.ident == wrapper)?;
+    let syn::PathArguments::AngleBracketed(args) = &seg.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        syn::GenericArgument::Type(elem) => Some(elem),
+        _ => None,
+    }
+}
+
+/// Generate a wrapper converting `FuncArgs` into the typed arguments of `f` using
+/// `ensan::functions::FromArg`, and the `FuncDef` of the wrapper.
+///
+/// The `#[opt]`, `#[opt = default]` and `#[variadic]` attributes on the parameters of `f` are
+/// removed.
+fn typed_fn(
+    f: &mut syn::ItemFn,
+) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+    let fname = &f.sig.ident;
+    let wrapper = quote::format_ident!("__ensan_typed_{fname}");
+    let (mut params, mut extract, mut call_args) = (vec![], vec![], vec![]);
+    let (mut optional, mut variadic) = (0_usize, None);
+    let len = f.sig.inputs.len();
+    for (pos, input) in f.sig.inputs.iter_mut().enumerate() {
+        let syn::FnArg::Typed(pt) = input else {
+            return Err(syn::Error::new(
+                input.span(),
+                "#[ensan_fn] cannot take `self`",
+            ));
+        };
+        let mut default = None;
+        let mut is_variadic = false;
+        let mut attrs = vec![];
+        for attr in pt.attrs.drain(..) {
+            match &attr.meta {
+                syn::Meta::Path(p) if p.is_ident("opt") => default = Some(None),
+                syn::Meta::NameValue(nv) if nv.path.is_ident("opt") => {
+                    default = Some(Some(nv.value.clone()));
+                }
+                syn::Meta::Path(p) if p.is_ident("variadic") => is_variadic = true,
+                _ => attrs.push(attr),
+            }
+        }
+        pt.attrs = attrs;
+        let ty = &*pt.ty;
+        let arg = quote::format_ident!("__arg{pos}");
+        let map_err = quote::quote! {
+            .map_err(|e| ::std::format!("argument at position {}: {e}", #pos))
+        };
+        if is_variadic {
+            let Some(elem) = generic_elem(ty, "Vec").filter(|_| pos + 1 == len && optional == 0)
+            else {
+                return Err(syn::Error::new(
+                    pt.span(),
+                    "#[variadic] must be the last parameter, of type `Vec<T>`, and cannot be \
+                     combined with #[opt]",
+                ));
+            };
+            variadic = Some(quote::quote! {
+                <#elem as ::ensan::functions::FromArg>::param_type()
+            });
+            extract.push(quote::quote! {
+                let #arg = (values[#pos..].iter().enumerate())
+                    .map(|(i, v)| {
+                        <#elem as ::ensan::functions::FromArg>::from_arg(v)
+                            .map_err(|e| ::std::format!("argument at position {}: {e}", #pos + i))
+                    })
+                    .collect::<::std::result::Result<::std::vec::Vec<_>, _>>()?;
+            });
+        } else if let Some(None) = default {
+            optional += 1;
+            let Some(elem) = generic_elem(ty, "Option") else {
+                return Err(syn::Error::new(
+                    pt.ty.span(),
+                    "#[opt] without a default must be on an `Option<T>` parameter",
+                ));
+            };
+            extract.push(quote::quote! {
+                let #arg = (values.get(#pos))
+                    .map(<#elem as ::ensan::functions::FromArg>::from_arg)
+                    .transpose() #map_err?;
+            });
+        } else if let Some(Some(default)) = default {
+            optional += 1;
+            let default_var = quote::format_ident!("__default{pos}");
+            extract.push(quote::quote! {
+                let #default_var = ::ensan::hcl::Value::from(#default);
+                let #arg = <#ty as ::ensan::functions::FromArg>::from_arg(
+                    values.get(#pos).filter(|v| !v.is_null()).unwrap_or(&#default_var),
+                ) #map_err?;
+            });
+        } else if optional == 0 {
+            params.push(quote::quote! { <#ty as ::ensan::functions::FromArg>::param_type() });
+            extract.push(quote::quote! {
+                let #arg = <#ty as ::ensan::functions::FromArg>::from_arg(&values[#pos]) #map_err?;
+            });
+        } else {
+            return Err(syn::Error::new(
+                pt.span(),
+                "required parameters cannot follow #[opt] ones",
+            ));
+        }
+        call_args.push(arg);
+    }
+    let mut check_len = quote::quote! {};
+    if optional > 0 {
+        // optional arguments are passed as variadic ones, so we check the count ourselves
+        variadic = Some(quote::quote! { ::ensan::hcl::eval::ParamType::Any });
+        let max = params.len() + optional;
+        check_len = quote::quote! {
+            if values.len() > #max {
+                return ::std::result::Result::Err(::std::format!(
+                    "expected at most {} positional arguments, got {}", #max, values.len()
+                ));
+            }
+        };
+    }
+    let variadic = variadic.iter();
+    Ok((
+        quote::quote! {
+            fn #wrapper(
+                args: ::ensan::hcl::eval::FuncArgs,
+            ) -> ::std::result::Result<::ensan::hcl::Value, ::std::string::String> {
+                let values: &[::ensan::hcl::Value] = &args;
+                #check_len
+                #(#extract)*
+                #fname(#(#call_args),*)
+                    .map(::ensan::hcl::Value::from)
+                    .map_err(::std::convert::Into::into)
+            }
+        },
+        quote::quote! {
+            ::ensan::hcl::eval::FuncDef::builder()
+                .params([#(#params),*])
+                #(.variadic_param(#variadic))*
+                .build(#wrapper)
+        },
+    ))
+}
+
+/// Declare a group of hcl functions from a module.
+///
+/// Every `pub fn` inside the module marked with `#[ensan_fn]` is collected into a new function
+/// (named by the macro argument) that returns an [`ensan::functions::FnGroup`]. The group can
+/// then be registered with `ensan::Engine::register_fns()`.
+///
+/// The parameter types and the conversion of the arguments are generated from the Rust types of
+/// the parameters (see [`ensan::functions::FromArg`]). Parameters marked with `#[opt = default]`
+/// are optional, and an explicit `null` also gives the default; `#[opt]` alone must be on an
+/// `Option<T>`, which is `None` only if the argument is not passed, so that an explicit `null`
+/// can be told apart. The last
+/// parameter can be marked with `#[variadic]` and must be a `Vec<T>`, which collects all the
+/// remaining arguments. The function may return any `Result<T, E>` where `T: Into<Value>` and
+/// `E: Into<String>`.
+///
+/// ```ignore
+/// #[ensan::ensan_fn_mod(my_fns)]
+/// pub mod my_mod {
+///     use ensan::hcl::Value;
+///
+///     #[ensan_fn]
+///     pub fn repeat(s: &str, #[opt = 2] times: usize) -> Result<Value, String> {
+///         Ok(s.repeat(times).into())
+///     }
+/// }
+///
+/// let mut en = ensan::Engine::new();
+/// en.register_fns(my_fns());
+/// ```
+///
+/// ### Untyped functions
+/// Functions may also take `FuncArgs` directly, in which case the parameter types are listed in
+/// `#[ensan_fn(...)]` as variants of `hcl::eval::ParamType`, e.g.
+/// `#[ensan_fn(String, Array(Number))]`. The last parameter may be variadic by prefixing it with
+/// `..`, e.g. `#[ensan_fn(String, ..Any)]` accepts a string followed by any number of arguments
+/// of any type.
+///
+/// Trailing parameters can be made optional with `opt Type = default`, or just `opt Type` if
+/// there is no default. The defaults are filled in before calling the function, also in place of
+/// `null`, e.g. `#[ensan_fn(String, opt Number = 10)]` always receives 2 arguments. Optional
+/// parameters without a default are left out if they are not passed.
+///
+/// ```ignore
+/// #[ensan_fn(String)]
+/// pub fn shout(args: FuncArgs) -> Result<Value, String> {
+///     Ok(args[0].as_str().unwrap_or_default().to_uppercase().into())
+/// }
+/// ```
+///
+/// [`ensan::functions::FnGroup`]: https://docs.rs/ensan/latest/ensan/functions/type.FnGroup.html
+/// [`ensan::functions::FromArg`]: https://docs.rs/ensan/latest/ensan/functions/trait.FromArg.html
 #[proc_macro_attribute]
-pub fn ensan_internal_fn_mod(args: TokenStream, input: TokenStream) -> TokenStream {
+pub fn ensan_fn_mod(args: TokenStream, input: TokenStream) -> TokenStream {
     let mut input = syn::parse_macro_input!(input as syn::ItemMod);
     let new_fn_name = syn::parse_macro_input!(args as syn::Ident);
     let mod_name = &input.ident;
-    let mut declare_func_stmts = vec![];
-    for elm in &mut input.content.iter_mut().next().unwrap().1 {
+    let Some((_, items)) = &mut input.content else {
+        return syn::Error::new(input.span(), "expected an inline module with a body")
+            .into_compile_error()
+            .into();
+    };
+    let mut funcs = vec![];
+    let mut wrappers = vec![];
+    for elm in items {
         let syn::Item::Fn(f) = elm else { continue };
-        let fname = &f.sig.ident;
+        let fname = f.sig.ident.clone();
         // ensure it is #[ensan_fn]
         let Some((attr_index, _)) =
             (f.attrs.iter().enumerate()).find(|(_, a)| a.path().is_ident("ensan_fn"))
         else {
             continue;
         };
-        let ensan_attr = f
-            .attrs
-            .swap_remove(attr_index)
+        let ensan_attr = f.attrs.swap_remove(attr_index);
+        if is_typed(&ensan_attr, f) {
+            match typed_fn(f) {
+                Ok((wrapper, funcdef)) => {
+                    wrappers.push(wrapper);
+                    funcs.push(quote::quote! { (stringify!(#fname), #funcdef) });
+                }
+                Err(e) => return e.into_compile_error().into(),
+            }
+            continue;
+        }
+        let ensan_attr = ensan_attr
             .parse_args()
             .unwrap_or_else(syn::Error::into_compile_error)
             .into();
         let ensan_attr = syn::parse_macro_input!(ensan_attr as EnsanFnAttrArgs);
         let params = ensan_attr.args.iter().map(|param| mutate_tokens(&[param]));
-        declare_func_stmts.push(quote::quote! {
-            ctx.declare_func(stringify!(#fname), ::hcl::eval::FuncDef::new(#fname, [#(#params),*]));
+        let mut variadic = (ensan_attr.variadic.iter())
+            .map(|param| mutate_tokens(&[param]))
+            .collect::<Vec<_>>();
+        let mut func = fname.clone();
+        if !ensan_attr.optional.is_empty() {
+            // optional parameters are passed as variadic ones, then checked by a wrapper which
+            // fills in the defaults before calling the actual function
+            func = quote::format_ident!("__ensan_opt_{fname}");
+            let required = ensan_attr.args.len();
+            let optional = ensan_attr.optional.iter().map(|(ty, default)| {
+                let ty = mutate_tokens(&[ty]);
+                let default = match default {
+                    Some(d) => quote::quote! {
+                        ::std::option::Option::Some(::ensan::hcl::Value::from(#d))
+                    },
+                    None => quote::quote! { ::std::option::Option::None },
+                };
+                quote::quote! { (#ty, #default) }
+            });
+            wrappers.push(quote::quote! {
+                fn #func(
+                    args: ::ensan::hcl::eval::FuncArgs,
+                ) -> ::std::result::Result<::ensan::hcl::Value, ::std::string::String> {
+                    let mut values = args.into_values();
+                    ::ensan::functions::fill_optional_args(&mut values, #required, [#(#optional),*])?;
+                    ::ensan::functions::call_with_values(#fname, values)
+                }
+            });
+            variadic.push(quote::quote! { ::ensan::hcl::eval::ParamType::Any });
+        }
+        funcs.push(quote::quote! {
+            (
+                stringify!(#fname),
+                ::ensan::hcl::eval::FuncDef::builder()
+                    .params([#(#params),*])
+                    #(.variadic_param(#variadic))*
+                    .build(#func),
+            )
         });
     }
+    let vis = &input.vis;
     quote::quote! {
         #input
-        pub fn #new_fn_name(ctx: &mut ::hcl::eval::Context) {
+        #vis fn #new_fn_name() -> ::ensan::functions::FnGroup {
             use #mod_name::*;
-            #(#declare_func_stmts)*
+            #(#wrappers)*
+            ::std::vec![#(#funcs),*]
         }
     }
     .into()
@@ -0,0 +1,263 @@
+//! # Diagnostics
+//!
+//! This module turns [`crate::Error`]s into [`Diagnostic`]s, which can be rendered for config
+//! authors with the offending source line, carets under the failing expression, the attribute
+//! path and hints:
+//!
+//! ```text
+//! error: undefined variable `nope`
+//!  --> main.hcl:4:15
+//!   |
+//! 4 |         hai = upper(nope)
+//!   |               ^^^^^^^^^^^
+//!   = in: blk "one" "two" > again > hai
+//! ```
+//!
+//! The snippets are taken from the [`Sources`] given for rendering, by the file names of the
+//! locations.
+//!
+//! # Examples
+//! ```
+//! let src = "foo = upper(1)";
+//! let err = ensan::Engine::new().parse_named("main.hcl", src).unwrap_err();
+//! let rendered = err.render(&[("main.hcl", src)], false);
+//! assert!(rendered.starts_with("error: error calling function `upper`"));
+//! assert!(rendered.contains("1 | foo = upper(1)\n  |       ^^^^^^^^"));
+//! ```
+use crate::errors::{Error, Location};
+use itertools::Itertools;
+use std::{borrow::Cow, collections::BTreeMap, collections::HashMap, fmt::Write};
+
+/// The hcl strings that diagnostics are rendered from, looked up by the file names of the
+/// locations, see [`Location::file`].
+///
+/// - a `str` is the string parsed without a file name, e.g. with [`crate::Engine::parse()`]
+/// - `[(file, text)]` and maps from file names to texts are the strings parsed with names, e.g.
+///   with [`crate::Engine::parse_named()`]
+/// - [`FileSystem`] reads the files, e.g. for [`crate::Engine::parse_dir()`]
+///
+/// Locations in a file that is not found are rendered without a snippet.
+pub trait Sources {
+    /// The text of `file`, or of the string parsed without a file name if `None`.
+    fn source(&self, file: Option<&str>) -> Option<Cow<'_, str>>;
+}
+
+impl Sources for str {
+    fn source(&self, file: Option<&str>) -> Option<Cow<'_, str>> {
+        file.is_none().then_some(Cow::Borrowed(self))
+    }
+}
+
+impl<F: AsRef<str>, T: AsRef<str>> Sources for [(F, T)] {
+    fn source(&self, file: Option<&str>) -> Option<Cow<'_, str>> {
+        let file = file?;
+        (self.iter())
+            .find(|(f, _)| f.as_ref() == file)
+            .map(|(_, text)| Cow::Borrowed(text.as_ref()))
+    }
+}
+
+impl<F: AsRef<str>, T: AsRef<str>, const N: usize> Sources for [(F, T); N] {
+    fn source(&self, file: Option<&str>) -> Option<Cow<'_, str>> {
+        self[..].source(file)
+    }
+}
+
+impl<F: AsRef<str> + Ord, T: AsRef<str>> Sources for BTreeMap<F, T> {
+    fn source(&self, file: Option<&str>) -> Option<Cow<'_, str>> {
+        let file = file?;
+        (self.iter())
+            .find(|(f, _)| f.as_ref() == file)
+            .map(|(_, text)| Cow::Borrowed(text.as_ref()))
+    }
+}
+
+impl<F: AsRef<str>, T: AsRef<str>, S> Sources for HashMap<F, T, S> {
+    fn source(&self, file: Option<&str>) -> Option<Cow<'_, str>> {
+        let file = file?;
+        (self.iter())
+            .find(|(f, _)| f.as_ref() == file)
+            .map(|(_, text)| Cow::Borrowed(text.as_ref()))
+    }
+}
+
+/// [`Sources`] reading the files named in the locations, for errors from
+/// [`crate::Engine::parse_files()`] and the files included from them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystem;
+
+impl Sources for FileSystem {
+    fn source(&self, file: Option<&str>) -> Option<Cow<'_, str>> {
+        std::fs::read_to_string(file?).ok().map(Cow::Owned)
+    }
+}
+
+/// How severe a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A problem found in the hcl strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// where the problem is, if known
+    pub location: Option<Location>,
+    /// extra help for fixing the problem
+    pub hints: Vec<String>,
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+impl Diagnostic {
+    /// Convert an error into diagnostics, one for each error in [`Error::Multiple`].
+    #[must_use]
+    pub fn from_error(err: &Error) -> Vec<Self> {
+        let mut diags = vec![];
+        Self::collect(err, None, &mut diags);
+        diags
+    }
+    fn collect(err: &Error, location: Option<&Location>, diags: &mut Vec<Self>) {
+        let message = match err {
+            Error::Suggestions {
+                source,
+                suggestions,
+            } => {
+                Self::collect(source, location, diags);
+                if let Some(diag) = diags.last_mut() {
+                    let names = suggestions.iter().map(|s| format!("`{s}`")).join(" or ");
+                    diag.hints.push(format!("did you mean {names}?"));
+                }
+                return;
+            }
+            Error::At { location, source } => return Self::collect(source, Some(location), diags),
+            Error::Multiple { errors, .. } => {
+                return errors
+                    .iter()
+                    .for_each(|e| Self::collect(e, location, diags));
+            }
+            Error::Hcl(hcl::Error::Parse(e)) => format!("syntax error: {}", e.message()),
+            Error::Hcl(hcl::Error::Eval(e)) | Error::HclEval(e) => e.kind().to_string(),
+            Error::Hcl(e) => e.to_string(),
+            Error::Io { .. }
+            | Error::Include { .. }
+            | Error::Variable { .. }
+            | Error::Validation { .. } => err.to_string(),
+            Error::HclEvals(errs) => errs.iter().map(hcl::eval::Error::kind).join("; "),
+            Error::Cycle(path) => format!("reference cycle: {}", path.join(" -> ")),
+            Error::IncludeCycle(files) => format!("include cycle: {}", files.join(" -> ")),
+            Error::Redefined { path, .. } => format!("`{path}` is already defined"),
+        };
+        let hints = match err {
+            Error::Cycle(_) => vec!["break the cycle by removing one of the references".into()],
+            Error::IncludeCycle(_) => {
+                vec!["break the cycle by removing one of the includes".into()]
+            }
+            Error::Redefined { previous, .. } => vec![format!("previously defined at {previous}")],
+            _ => vec![],
+        };
+        diags.push(Self {
+            severity: Severity::Error,
+            message,
+            location: location.cloned(),
+            hints,
+        });
+    }
+
+    /// Render the diagnostic with a snippet from the string of `sources` the location is in, if
+    /// found.
+    ///
+    /// If `color` is set, ANSI escape codes are used for coloring the output in terminals.
+    #[must_use]
+    pub fn render(&self, sources: &(impl Sources + ?Sized), color: bool) -> String {
+        let paint = |style: &str, s: &str| {
+            if color {
+                format!("{style}{s}{RESET}")
+            } else {
+                s.to_owned()
+            }
+        };
+        let level_style = match self.severity {
+            Severity::Warning => YELLOW,
+            Severity::Error => RED,
+        };
+        let mut out = format!(
+            "{}{}",
+            paint(level_style, &self.severity.to_string()),
+            paint(BOLD, &format!(": {}", self.message)),
+        );
+        let mut pad = String::new();
+        if let Some(loc) = &self.location {
+            let line_no = loc.line.to_string();
+            pad = " ".repeat(line_no.len());
+            let gutter = paint(BLUE, &format!("{pad} |"));
+            _ = write!(out, "\n{pad}{} ", paint(BLUE, "-->"));
+            if let Some(file) = &loc.file {
+                _ = write!(out, "{file}:");
+            }
+            _ = write!(out, "{}:{}", loc.line, loc.column);
+            let source = sources.source(loc.file.as_deref()).unwrap_or_default();
+            if let Some(line) = source.lines().nth(loc.line.saturating_sub(1)) {
+                // only underline the first line of expressions spanning multiple lines
+                let col = loc.column.saturating_sub(1);
+                let width = (source.get(loc.span.clone()).unwrap_or_default())
+                    .lines()
+                    .next()
+                    .map_or(0, |s| s.chars().count())
+                    .min(line.chars().count().saturating_sub(col))
+                    .max(1);
+                _ = write!(
+                    out,
+                    "\n{gutter}\n{} {line}\n{gutter} {}{}",
+                    paint(BLUE, &format!("{line_no} |")),
+                    " ".repeat(col),
+                    paint(level_style, &"^".repeat(width)),
+                );
+            }
+            if !loc.path.is_empty() {
+                _ = write!(out, "\n{pad} {} in: {}", paint(BLUE, "="), loc.path);
+            }
+        }
+        for hint in &self.hints {
+            _ = write!(out, "\n{pad} {} hint: {hint}", paint(BLUE, "="));
+        }
+        out
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+        if let Some(loc) = &self.location {
+            write!(f, " at {loc}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    /// Render the error as [`Diagnostic`]s, see [`Diagnostic::render()`].
+    #[must_use]
+    pub fn render(&self, sources: &(impl Sources + ?Sized), color: bool) -> String {
+        Diagnostic::from_error(self)
+            .iter()
+            .map(|diag| diag.render(sources, color))
+            .join("\n\n")
+    }
+}
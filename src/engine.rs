@@ -3,6 +3,106 @@
 //! This module contains the [`Engine`] implementation.
 //! You may use the engine to parse hcl-formatted strings.
 //!
+//! Attributes are evaluated in the order of their dependencies instead of top-to-bottom, so an
+//! attribute may reference other attributes or blocks that are defined later in the document.
+//!
+//! Attributes inside a block see the other attributes of the same block by name. The built-in
+//! objects `self`, `parent` and `root` refer to the block, the block containing it and the whole
+//! document explicitly. The attributes of the top-level `locals` blocks are available everywhere as
+//! `local.<name>`:
+//! ```
+//! let body = ensan::parse(r#"
+//! name = "app"
+//! svc "web" {
+//!     name = "web"
+//!     full = "${root.name}-${self.name}"
+//! }
+//! "#).unwrap();
+//! let expected = ensan::parse(r#"
+//! name = "app"
+//! svc "web" {
+//!     name = "web"
+//!     full = "app-web"
+//! }
+//! "#).unwrap();
+//! assert_eq!(body, expected);
+//! ```
+//!
+//! Blocks with a `for_each` or `count` attribute are expanded into one block per item, with
+//! `each.key`/`each.value` or `count.index` in scope:
+//! ```
+//! let body = ensan::parse(r#"
+//! svc {
+//!     for_each = { a = 80, b = 443 }
+//!     addr = "${each.key}:${each.value}"
+//! }
+//! first = svc.a.addr
+//! "#).unwrap();
+//! let expected = r#"
+//! svc "a" {
+//!     addr = "a:80"
+//! }
+//! svc "b" {
+//!     addr = "b:443"
+//! }
+//! first = "a:80"
+//! "#;
+//! assert_eq!(body, hcl::parse(expected).unwrap());
+//! ```
+//!
+//! Similarly, `dynamic "ident" { for_each = ..., content { ... } }` generates an `ident` block
+//! from `content` for each item, with `ident.key` and `ident.value` in scope. Any block can be left
+//! out of the output with `_enabled = false`, which is prefixed so that it does not collide with
+//! ordinary `enabled` attributes.
+//!
+//! A `variable "name" { type = ..., default = ... }` block declares an input available everywhere
+//! as `var.<name>`. Its value is set from outside with [`Engine::set_var()`] (or
+//! [`Engine::set_vars_from_env()`] and [`Engine::set_vars_from_file()`]), otherwise it is the
+//! `default`, and it is checked against the `type`:
+//! ```
+//! let mut en = ensan::Engine::new();
+//! en.set_var("var.replicas", "3").unwrap();
+//! let body = en.parse(r#"
+//! variable "replicas" {
+//!     type = number
+//! }
+//! variable "region" {
+//!     type = string
+//!     default = "eu-west-1"
+//! }
+//! svc {
+//!     replicas = var.replicas * 2
+//!     region = var.region
+//! }
+//! "#).unwrap();
+//! let svc = body.blocks().nth(2).unwrap();
+//! let expected = ensan::parse("replicas = 6\nregion = \"eu-west-1\"").unwrap();
+//! assert_eq!(svc.body, expected);
+//! ```
+//!
+//! A `validation { condition = ..., error_message = ... }` block fails the evaluation with the
+//! `error_message` if its `condition` is false. In a `variable` block, it is checked once the value
+//! of the variable is known; elsewhere, the `condition` is evaluated in the scope of the body
+//! containing it:
+//! ```
+//! let err = ensan::parse(r#"
+//! svc {
+//!     replicas = 0
+//!     validation {
+//!         condition = replicas > 0
+//!         error_message = "svc needs at least one replica"
+//!     }
+//! }
+//! "#).unwrap_err();
+//! assert_eq!(
+//!     err.to_string(),
+//!     "5:21 (svc > validation > condition): svc needs at least one replica",
+//! );
+//! ```
+//!
+//! An `include "path/to/other.hcl" {}` (or `import`) block is replaced with the contents of the
+//! file at the path, which is relative to the including file, see [`Engine::parse_file()`].
+//!
 //! # Examples
 //! ```
 //! use ensan::Engine;
@@ -45,12 +145,22 @@
 //! assert_eq!(body, body2);
 //! ```
 
+use crate::{
+    diagnostics::{Diagnostic, Severity},
+    errors::Location,
+};
 use core::borrow::BorrowMut;
 use hcl::{
-    eval::{Context, Evaluate},
+    edit::Span,
+    eval::{Context, ErrorKind, Evaluate, Func, FuncDef, ParamType},
     Value,
 };
 use itertools::Itertools;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
 
 /// Internal result type
 type Res<T> = Result<T, crate::Error>;
@@ -60,6 +170,9 @@ type Res<T> = Result<T, crate::Error>;
 pub enum VarScope {
     Var(String, Value),
     Scope(String, VarScopes),
+    /// blocks without labels, e.g. `rule {}`, which are referenced by index like `rule[1]`, see
+    /// [`Engine::block_lists`]
+    List(String, Vec<VarScopes>),
 }
 
 impl VarScope {
@@ -79,6 +192,61 @@ impl VarScope {
             _ => None,
         }
     }
+    /// Enter the scope at the start of `path`, returning it along with the rest of the path.
+    ///
+    /// For [`Self::List`], the segment following the key is the index of the item, e.g.
+    /// `["rule", "1", "name"]` enters the second `rule` block, leaving `["name"]`.
+    #[must_use]
+    pub fn enter_ref<'a, 'p, S: AsRef<str>>(
+        &'a self,
+        path: &'p [S],
+    ) -> Option<(&'a VarScopes, &'p [S])> {
+        let [first, rest @ ..] = path else {
+            return None;
+        };
+        match self {
+            Self::Scope(key, varscopes) if key == first.as_ref() => Some((varscopes, rest)),
+            Self::List(key, items) if key == first.as_ref() => {
+                let [idx, rest @ ..] = rest else {
+                    return None;
+                };
+                Some((items.get(idx.as_ref().parse::<usize>().ok()?)?, rest))
+            }
+            _ => None,
+        }
+    }
+    /// Enter the scope at the start of `path`, see [`Self::enter_ref()`].
+    #[must_use]
+    pub fn enter_mut<'a, 'p, S: AsRef<str>>(
+        &'a mut self,
+        path: &'p [S],
+    ) -> Option<(&'a mut VarScopes, &'p [S])> {
+        let [first, rest @ ..] = path else {
+            return None;
+        };
+        match self {
+            Self::Scope(key, varscopes) if key == first.as_ref() => Some((varscopes, rest)),
+            Self::List(key, items) if key == first.as_ref() => {
+                let [idx, rest @ ..] = rest else {
+                    return None;
+                };
+                Some((items.get_mut(idx.as_ref().parse::<usize>().ok()?)?, rest))
+            }
+            _ => None,
+        }
+    }
+    /// The name and the value of the variable.
+    #[must_use]
+    pub fn to_hcl_var(&self) -> (String, Value) {
+        match self {
+            Self::Var(k, v) => (k.clone(), v.clone()),
+            Self::Scope(k, v) => (k.clone(), v.to_hcl_value()),
+            Self::List(k, items) => (
+                k.clone(),
+                Value::Array(items.iter().map(VarScopes::to_hcl_value).collect()),
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -101,15 +269,15 @@ impl VarScopes {
     pub fn list_in_scope_mut<'a>(
         &'a mut self,
         scope: &'a [&str],
-    ) -> Box<dyn Iterator<Item = &'_ mut VarScope> + 'a> {
-        let [first, remaining @ ..] = scope else {
+    ) -> Box<dyn Iterator<Item = &'a mut VarScope> + 'a> {
+        if scope.is_empty() {
             return Box::new(self.0.iter_mut());
-        };
+        }
         Box::new(
             self.0
                 .iter_mut()
-                .filter_map(|varscope| varscope.get_scope_mut(first))
-                .flat_map(|varscopes| varscopes.list_in_scope_mut(remaining)),
+                .filter_map(|varscope| varscope.enter_mut(scope))
+                .flat_map(|(varscopes, remaining)| varscopes.list_in_scope_mut(remaining)),
         )
     }
     /// List all variables that reside in the given scope.
@@ -121,47 +289,70 @@ impl VarScopes {
     pub fn list_in_scope_ref<'a>(
         &'a self,
         scope: &'a [impl AsRef<str>],
-    ) -> Box<dyn Iterator<Item = &'_ VarScope> + 'a> {
-        let [first, remaining @ ..] = scope else {
+    ) -> Box<dyn Iterator<Item = &'a VarScope> + 'a> {
+        if scope.is_empty() {
             return Box::new(self.0.iter());
-        };
+        }
         Box::new(
             self.0
                 .iter()
-                .filter_map(|varscope| varscope.get_scope_ref(first.as_ref()))
-                .flat_map(|varscopes| varscopes.list_in_scope_ref(remaining)),
+                .filter_map(|varscope| varscope.enter_ref(scope))
+                .flat_map(|(varscopes, remaining)| varscopes.list_in_scope_ref(remaining)),
         )
     }
     #[must_use]
     pub fn to_hcl_value(&self) -> Value {
-        let mut indexmap = hcl::Map::new();
-        self.0.iter().for_each(|x| match x {
-            VarScope::Var(k, v) => _ = indexmap.insert(k.clone(), v.clone()),
-            VarScope::Scope(k, v) => _ = indexmap.insert(k.clone(), v.to_hcl_value()),
-        });
-        Value::Object(indexmap)
+        Value::Object(self.0.iter().map(VarScope::to_hcl_var).collect())
+    }
+    /// The variables in the given scope as an object, see [`Self::list_in_scope_ref()`].
+    #[must_use]
+    pub fn scope_to_hcl_value(&self, scope: &[impl AsRef<str>]) -> Value {
+        Value::Object(
+            (self.list_in_scope_ref(scope))
+                .map(VarScope::to_hcl_var)
+                .collect(),
+        )
     }
     pub fn populate_hcl_ctx(&self, ctx: &mut Context, scope: &[impl AsRef<str>]) {
-        self.list_in_scope_ref(scope)
-            .for_each(|varscopes| match varscopes {
-                VarScope::Var(k, v) => ctx.declare_var(k.to_string(), v.to_owned()),
-                VarScope::Scope(k, v) => ctx.declare_var(k.to_string(), v.to_hcl_value()),
-            });
+        self.list_in_scope_ref(scope).for_each(|varscope| {
+            let (k, v) = varscope.to_hcl_var();
+            ctx.declare_var(k, v);
+        });
     }
     #[must_use]
-    pub fn to_hcl_ctx(&self, scope: &[impl AsRef<str>]) -> Context {
+    pub fn to_hcl_ctx(&self, scope: &[impl AsRef<str>]) -> Context<'_> {
         let mut ctx = Context::new();
         self.populate_hcl_ctx(&mut ctx, scope);
         ctx
     }
-    fn _set_populate(&mut self, scope: &[String], key: String, value: Value) {
-        let [first, rest @ ..] = scope else {
-            self.0.push(VarScope::Var(key, value));
+    /// Get the given scope, creating the missing scopes along the way.
+    fn scope_mut(&mut self, scope: &[String]) -> &mut Self {
+        if scope.is_empty() {
+            return self;
+        }
+        let pos = self.0.iter().position(|s| s.enter_ref(scope).is_some());
+        let pos = pos.unwrap_or_else(|| {
+            self.0
+                .push(VarScope::Scope(scope[0].clone(), Self::default()));
+            self.0.len() - 1
+        });
+        match self.0[pos].enter_mut(scope) {
+            Some((s, rest)) => s.scope_mut(rest),
+            None => unreachable!("the scope was just found or created"),
+        }
+    }
+    /// Remove a variable from the given scope, if it exists.
+    ///
+    /// Scopes that become empty are left in place.
+    pub fn remove(&mut self, scope: &[String], key: &str) {
+        if scope.is_empty() {
+            self.0
+                .retain(|v| !matches!(v, VarScope::Var(k, _) if k == key));
             return;
-        };
-        let mut new = Self::default();
-        new._set_populate(rest, key, value);
-        self.0.push(VarScope::Scope(first.to_string(), new));
+        }
+        (self.0.iter_mut())
+            .filter_map(|s| s.enter_mut(scope))
+            .for_each(|(s, rest)| s.remove(rest, key));
     }
     /// Create a new variable and set the value.
     ///
@@ -169,115 +360,1910 @@ impl VarScopes {
     /// However, if you call this twice with another `value`, the old value would be overwritten
     /// during `to_hcl_value()` and `to_hcl_ctx()`.
     pub fn set(&mut self, scope: &[String], key: String, value: Value) {
-        let [first, rest @ ..] = scope else {
-            self.0.push(VarScope::Var(key, value));
-            return;
+        self.scope_mut(scope).0.push(VarScope::Var(key, value));
+    }
+    /// Create a list of `len` empty scopes, see [`VarScope::List`].
+    ///
+    /// The items can then be populated with [`Self::set()`] using the index in the scope, e.g.
+    /// `["rule", "1"]`.
+    pub fn set_list(&mut self, scope: &[String], key: String, len: usize) {
+        self.scope_mut(scope)
+            .0
+            .push(VarScope::List(key, vec![Self::default(); len]));
+    }
+}
+
+/// Attributes controlling how a block is expanded instead of being part of its body, see
+/// [`Engine::expand()`].
+const META_ARGS: [&str; 3] = ["for_each", "count", "_enabled"];
+
+/// Names of the built-in objects, see [`Engine::evaluate()`].
+const BUILTINS: [&str; 5] = ["self", "parent", "root", "local", "var"];
+
+/// What a [`Node`] stands for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NodeKind {
+    Attr,
+    /// a block with meta-arguments, with its labels
+    Block(Vec<String>),
+    /// a `variable "name"` block, bound to `var.<name>`, see [`Engine::bind_variable()`]
+    Variable,
+    /// a `validation` block, see [`Engine::validate()`]
+    Validation,
+}
+
+/// An attribute, a block with meta-arguments, a `variable` block or a `validation` block in the
+/// reference graph.
+#[derive(Debug, Clone)]
+struct Node {
+    /// indices of the structures leading to the node, see [`Engine::at_mut()`]
+    addr: Vec<usize>,
+    /// scope of the block containing the node
+    scope: Vec<String>,
+    /// key of the attribute, or identifier of the block
+    key: String,
+    kind: NodeKind,
+    /// references relative to `scope`
+    refs: Vec<crate::graph::Reference>,
+    /// names of the called functions
+    funcs: Vec<String>,
+    /// the blocks containing the attribute as written, e.g. `blk "one" "two"`, including the
+    /// block itself for [`NodeKind::Block`]
+    frames: Vec<String>,
+    /// lengths of `scope` where each of the blocks containing the node begins, for resolving
+    /// `parent`
+    bounds: Vec<usize>,
+    /// byte offsets of the expression, of the first meta-argument of a block, of the identifier
+    /// of a `variable` block, or of the `condition` of a `validation` block
+    span: Range<usize>,
+}
+
+impl Node {
+    /// The path referenced by `r`, resolving the built-in `self`, `parent`, `root`, `local` and
+    /// `var` objects, see [`Engine::evaluate()`].
+    ///
+    /// `shadowed` tells whether a name is defined in the scope, which then takes precedence over
+    /// the built-in object of that name.
+    fn resolve<'r>(&'r self, r: &'r [String], shadowed: impl Fn(&str) -> bool) -> Vec<&'r str> {
+        let (base, rest) = match r {
+            [first, ..] if BUILTINS.contains(&first.as_str()) && shadowed(first) => {
+                (&self.scope[..], r)
+            }
+            [first, ..] if first == "local" || first == "var" => (&[][..], r),
+            [first, rest @ ..] if first == "self" => (&self.scope[..], rest),
+            [first, rest @ ..] if first == "root" => (&[][..], rest),
+            [first, rest @ ..] if first == "parent" => match self.bounds.last() {
+                Some(&len) => (&self.scope[..len], rest),
+                None => (&self.scope[..], r),
+            },
+            _ => (&self.scope[..], r),
         };
-        if let Some(s) = self.0.iter_mut().find_map(|s| s.get_scope_mut(first)) {
-            s.set(rest, key, value);
-        } else {
-            let mut new = Self::default();
-            new._set_populate(rest, key, value);
-            self.0.push(VarScope::Scope(first.to_string(), new));
+        base.iter().chain(rest).map(String::as_str).collect()
+    }
+    fn path(&self) -> impl Iterator<Item = &str> {
+        let (var, labels) = match &self.kind {
+            NodeKind::Attr | NodeKind::Validation => (None, &[][..]),
+            NodeKind::Block(labels) => (None, &labels[..]),
+            NodeKind::Variable => (Some("var"), &[][..]),
+        };
+        (self.scope.iter().map(String::as_str))
+            .chain(var)
+            .chain([self.key.as_str()])
+            .chain(labels.iter().map(String::as_str))
+    }
+    fn location(&self, src: &Source<'_>) -> Location {
+        let key = (self.kind == NodeKind::Attr).then_some(self.key.as_str());
+        let path = (self.frames.iter().map(String::as_str))
+            .chain(key)
+            .join(" > ");
+        src.locate(&self.addr, self.span.clone(), path)
+    }
+}
+
+/// Everything found in a body by [`Engine::collect_nodes()`].
+#[derive(Debug, Default)]
+struct Collected {
+    nodes: Vec<Node>,
+    /// blocks without labels as `(scope, identifier, count)`, see [`VarScopes::set_list()`]
+    lists: Vec<(Vec<String>, String, usize)>,
+    /// paths of the labeled blocks, e.g. `["service"]` for `service "a" {}`, which are
+    /// objects keyed by the labels
+    labeled: BTreeSet<Vec<String>>,
+    /// whether to collect the blocks without labels into `lists`, see [`Engine::block_lists`]
+    block_lists: bool,
+}
+
+/// The hcl strings being parsed, used for locating errors.
+#[derive(Default)]
+struct Source<'s> {
+    /// the parsed strings as `(file, text)`, which are evaluated as a single body, including the
+    /// included files, see [`Engine::load()`]
+    files: Vec<(Option<Cow<'s, str>>, Cow<'s, str>)>,
+    /// indices into `files` of the strings containing the structures, by their address
+    origins: BTreeMap<Vec<usize>, usize>,
+    /// byte offsets of the attribute expressions and of the block identifiers by their address,
+    /// see [`Node::addr`]
+    spans: BTreeMap<Vec<usize>, Range<usize>>,
+    /// the canonical [`Engine::include_dir`]
+    include_dir: Option<std::path::PathBuf>,
+}
+
+impl Source<'_> {
+    /// Locate `span` in the string containing the structure at `addr`.
+    fn locate(&self, addr: &[usize], span: Range<usize>, path: String) -> Location {
+        let file = (0..=addr.len())
+            .rev()
+            .find_map(|len| self.origins.get(&addr[..len]));
+        match file.and_then(|&i| self.files.get(i)) {
+            Some((file, text)) => Location::new(file.as_deref(), text, span, path),
+            None => Location::new(None, "", span, path),
+        }
+    }
+}
+
+/// Where a body evaluated by [`Engine::parse_body()`] is in the hcl strings.
+#[derive(Debug, Default)]
+struct Origin {
+    /// address of the body, see [`Node::addr`]
+    addr: Vec<usize>,
+    /// the blocks containing the body as written
+    frames: Vec<String>,
+    /// see [`Node::bounds`]
+    bounds: Vec<usize>,
+    /// whether the body is an instance of a block with meta-arguments, which are left out
+    instance: bool,
+}
+
+/// The context for evaluating the attributes in a scope, kept up to date as the variables are
+/// set instead of being rebuilt for every attribute, see [`Engine::evaluate()`].
+#[derive(Debug, Clone)]
+struct ScopeCtx<'a> {
+    ctx: Context<'a>,
+    /// names of the variables in the scope, which take precedence over the built-in objects
+    names: BTreeSet<String>,
+    /// names of the variables that changed since they were declared in `ctx`
+    stale: BTreeSet<String>,
+}
+
+impl<'a> ScopeCtx<'a> {
+    fn new(ctx_init: &Context<'a>, varlist: &VarScopes, scope: &[String]) -> Self {
+        let mut ctx = ctx_init.clone();
+        let mut names = BTreeSet::new();
+        varlist.list_in_scope_ref(scope).for_each(|varscope| {
+            let (k, v) = varscope.to_hcl_var();
+            names.insert(k.clone());
+            ctx.declare_var(k, v);
+        });
+        Self {
+            ctx,
+            names,
+            stale: BTreeSet::new(),
+        }
+    }
+    /// Redeclare the stale variables from `varlist`.
+    fn refresh(&mut self, varlist: &VarScopes, scope: &[String]) {
+        if self.stale.is_empty() {
+            return;
+        }
+        // declared in order since the last one wins, see `VarScopes::set()`
+        for varscope in varlist.list_in_scope_ref(scope) {
+            let (VarScope::Var(k, _) | VarScope::Scope(k, _) | VarScope::List(k, _)) = varscope;
+            if self.stale.contains(k) {
+                let (k, v) = varscope.to_hcl_var();
+                self.ctx.declare_var(k, v);
+            }
         }
+        self.stale.clear();
     }
 }
 
+/// The keys of the instances of a block (`None` for `count`) along with the values of the
+/// iterator variable, see [`Engine::iterations()`].
+type Iterations = Vec<(Option<String>, Value)>;
+
+/// An error for a meta-argument evaluated to an unexpected value.
+fn unexpected(val: Value, expected: &'static str, location: Location) -> crate::Error {
+    crate::Error::from(hcl::eval::Error::from(ErrorKind::Unexpected(val, expected))).at(location)
+}
+
 /// Engine for parsing hcl strings
 #[derive(Debug, Clone, Default)]
 #[non_exhaustive]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Engine<'a> {
     pub ctx_init: Context<'a>,
     /// (variable) scope during parsing
     pub scope: Vec<String>,
     /// variable list
     pub varlist: VarScopes,
+    /// variables supplied from outside of the hcl strings, see [`Self::set_var()`]
+    ///
+    /// These are kept across [`Self::clean_up()`].
+    pub inputs: VarScopes,
+    /// keep evaluating the other attributes after an attribute fails to evaluate
+    ///
+    /// All the errors are then returned together as [`crate::Error::Multiple`], along with the
+    /// partially evaluated body. Attributes depending on a failed one are skipped without
+    /// reporting another error for them.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut en = ensan::Engine::new();
+    /// en.collect_errors = true;
+    /// let err = en.parse("a = nope\nb = a\nc = 1\nd = upper(1)").unwrap_err();
+    /// let ensan::Error::Multiple { errors, body } = err else { panic!() };
+    /// assert_eq!(errors.len(), 2); // `a` and `d`
+    /// assert_eq!(body.attributes().nth(2).unwrap().expr, 1.into());
+    /// ```
+    pub collect_errors: bool,
+    /// leave the `locals` blocks at the top level of the document out of the output
+    ///
+    /// The attributes of the `locals` blocks are referenced as `local.<name>`, and multiple
+    /// `locals` blocks are merged.
+    ///
+    /// # Examples
+    /// ```
+    /// let mut en = ensan::Engine::new();
+    /// en.strip_locals = true;
+    /// let hcl = "locals {\n  a = 1\n}\nlocals {\n  b = local.a + 1\n}\nx = local.b";
+    /// assert_eq!(en.parse(hcl).unwrap(), ensan::parse("x = 2").unwrap());
+    /// ```
+    pub strip_locals: bool,
+    /// fail on attributes that are defined more than once in the same scope
+    ///
+    /// This happens when blocks are repeated, e.g. two `blk "a" { x = 1 }`, or when an
+    /// attribute conflicts with a block, e.g. `blk = {}` and `blk "a" { x = 1 }`. Without this, the
+    /// last value silently wins. The errors are [`crate::Error::Redefined`].
+    ///
+    /// # Examples
+    /// ```
+    /// let mut en = ensan::Engine::new();
+    /// en.strict = true;
+    /// let err = en.parse("blk \"a\" {\n  x = 1\n}\nblk \"a\" {\n  x = 2\n}").unwrap_err();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     r#"5:7 (blk "a" > x): `blk.a.x` conflicts with the definition at 2:7 (blk "a" > x)"#,
+    /// );
+    /// ```
+    pub strict: bool,
+    /// reference the blocks without labels as lists, e.g. `rule[0].name` and `length(rule)`
+    ///
+    /// Every block without labels is an item of the list of the blocks with its identifier in the
+    /// same body, even when there is only one, so the references keep working as blocks are added
+    /// or removed. Without this, such blocks are objects like `rule.name`, and the attributes of
+    /// a repeated block overwrite the ones before it, see [`Self::strict`].
+    ///
+    /// # Examples
+    /// ```
+    /// let mut en = ensan::Engine::new();
+    /// en.block_lists = true;
+    /// let hcl = "rule {\n  name = \"a\"\n}\nn = length(rule)\nfirst = rule[0].name";
+    /// let body = en.parse(hcl).unwrap();
+    /// let expected = "rule {\n  name = \"a\"\n}\nn = 1\nfirst = \"a\"";
+    /// assert_eq!(body, ensan::parse(expected).unwrap());
+    /// ```
+    pub block_lists: bool,
+    /// the directory containing the files that `include "path" {}` and `import "path" {}` blocks
+    /// may load
+    ///
+    /// Includes are rejected unless this is set, so that parsing a string never reads files by
+    /// itself. The paths are resolved against the directory of the including file, and must lead
+    /// inside this directory once `..` and symlinks are resolved. The errors are
+    /// [`crate::Error::Include`].
+    ///
+    /// # Examples
+    /// ```
+    /// let err = ensan::parse("include \"/etc/hostname\" {}").unwrap_err();
+    /// assert!(matches!(err.inner(), ensan::Error::Include { .. }));
+    /// ```
+    pub include_dir: Option<std::path::PathBuf>,
+    /// names of the registered functions, for suggesting them on typos since `ctx_init` does not
+    /// expose them
+    fn_names: BTreeSet<String>,
+    /// deprecated functions and their deprecation notes, see [`Self::deprecate_fn()`]
+    deprecated_fns: BTreeMap<String, String>,
+    /// the contexts of the scopes evaluated so far, see [`ScopeCtx`]
+    ///
+    /// A new map is pushed for each instance of an expanded block, since the iterator variables
+    /// are declared in `ctx_init` then, see [`Self::expand()`].
+    contexts: Vec<BTreeMap<Vec<String>, ScopeCtx<'a>>>,
+    /// the values of the built-in objects by their paths, e.g. `[]` for `root`, kept up to date
+    /// as the variables are set, see [`Self::touch()`]
+    objects: BTreeMap<Vec<String>, Value>,
 }
 
 impl Engine<'_> {
     #[must_use]
     pub fn new() -> Self {
-        let mut ctx_init = Context::new();
-        Self::init_ctx(&mut ctx_init);
-        Self {
-            ctx_init,
-            ..Default::default()
-        }
+        let mut en = Self::default();
+        en.init_ctx();
+        en
     }
     /// Clean up the engine for parsing some other hcl strings.
     /// This does not reinitialize `ctx_init`.
     pub fn clean_up(&mut self) -> &mut Self {
         self.scope = vec![];
-        self.varlist = VarScopes::default();
+        self.varlist = self.inputs.clone();
+        self.forget_contexts();
+        self
+    }
+    /// Set a variable that can be referenced by the hcl strings parsed afterwards.
+    ///
+    /// `path` is a dot-separated path, so setting `var.region` would make the value available
+    /// as `var.region`. Setting the same path again replaces the old value. The variables are
+    /// kept across [`Self::clean_up()`].
+    ///
+    /// # Errors
+    /// The value cannot be serialized into an [`hcl::Value`].
+    ///
+    /// # Examples
+    /// ```
+    /// let mut en = ensan::Engine::new();
+    /// en.set_var("var.region", "eu-west-1").unwrap();
+    /// en.set_var("ports", [80, 443]).unwrap();
+    /// let body = en.parse(r#"x = "${var.region}:${ports[1]}""#).unwrap();
+    /// assert_eq!(body, ensan::parse(r#"x = "eu-west-1:443""#).unwrap());
+    /// ```
+    pub fn set_var(
+        &mut self,
+        path: impl AsRef<str>,
+        value: impl serde::Serialize,
+    ) -> Res<&mut Self> {
+        let value = hcl::to_value(value)?;
+        let mut scope = path.as_ref().split('.').map(str::to_owned).collect_vec();
+        let key = scope.pop().unwrap_or_default();
+        self.set_input(&scope, &key, &value);
+        Ok(self)
+    }
+    fn set_input(&mut self, scope: &[String], key: &str, value: &Value) {
+        for vs in [&mut self.inputs, &mut self.varlist] {
+            vs.remove(scope, key);
+            vs.set(scope, key.to_owned(), value.clone());
+        }
+    }
+    /// Set the values of the `variable` blocks from the environment variables named `prefix`
+    /// followed by the name of the variable, see [`Self::set_var()`].
+    ///
+    /// The values are strings, which are converted to the `type` of the variables, e.g. `8080`
+    /// for `number` or `["a", "b"]` for `list(string)`.
+    ///
+    /// # Examples
+    /// ```
+    /// std::env::set_var("MY_APP_port", "8080");
+    /// let mut en = ensan::Engine::new();
+    /// en.set_vars_from_env("MY_APP_");
+    /// let body = en.parse("variable \"port\" {\n  type = number\n}\nx = var.port + 1").unwrap();
+    /// assert_eq!(body.attributes().next().unwrap().expr, 8081.into());
+    /// ```
+    pub fn set_vars_from_env(&mut self, prefix: impl AsRef<str>) -> &mut Self {
+        let vars = std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        });
+        for (name, value) in vars {
+            if let Some(name) = name.strip_prefix(prefix.as_ref()) {
+                self.set_input(&["var".to_owned()], name, &Value::String(value));
+            }
+        }
+        self
+    }
+    /// Set the values of the `variable` blocks from the attributes of an hcl file, e.g.
+    /// `region = "eu-west-1"` sets `var.region`, see [`Self::set_var()`].
+    ///
+    /// The file is evaluated with the functions of the engine but none of its variables.
+    ///
+    /// # Errors
+    /// The file cannot be read or evaluated.
+    pub fn set_vars_from_file(&mut self, path: impl AsRef<std::path::Path>) -> Res<&mut Self> {
+        let mut en = self.clone();
+        en.inputs = VarScopes::default();
+        en.clean_up();
+        let body = en.parse_file(path)?;
+        for attr in body.attributes() {
+            let value = Value::from(attr.expr.clone());
+            self.set_input(&["var".to_owned()], &attr.key, &value);
+        }
+        Ok(self)
+    }
+    /// Set multiple variables at once, see [`Self::set_var()`].
+    ///
+    /// # Errors
+    /// Any of the values cannot be serialized into an [`hcl::Value`].
+    ///
+    /// # Examples
+    /// ```
+    /// let mut en = ensan::Engine::new().with_vars([("var.a", 1), ("var.b", 2)]).unwrap();
+    /// let body = en.parse("sum = var.a + var.b").unwrap();
+    /// assert_eq!(body, ensan::parse("sum = 3").unwrap());
+    /// ```
+    pub fn with_vars<K, V>(mut self, vars: impl IntoIterator<Item = (K, V)>) -> Res<Self>
+    where
+        K: AsRef<str>,
+        V: serde::Serialize,
+    {
+        for (path, value) in vars {
+            self.set_var(path, value)?;
+        }
+        Ok(self)
+    }
+    /// Register a custom function that can be called from the hcl strings.
+    ///
+    /// Registered functions are kept across [`Self::clean_up()`]. Registering a function with the
+    /// name of an existing one replaces it, including the built-in ones.
+    ///
+    /// # Examples
+    /// ```
+    /// use hcl::{eval::{FuncArgs, FuncDef, ParamType}, Value};
+    ///
+    /// fn double(args: FuncArgs) -> Result<Value, String> {
+    ///     Ok((args[0].as_i64().unwrap_or_default() * 2).into())
+    /// }
+    ///
+    /// let mut en = ensan::Engine::new();
+    /// en.register_fn("double", FuncDef::new(double, [ParamType::Number]));
+    /// let body = en.parse("x = double(21)").unwrap();
+    /// assert_eq!(body, ensan::parse("x = 42").unwrap());
+    /// ```
+    pub fn register_fn(&mut self, name: impl Into<String>, func: FuncDef) -> &mut Self {
+        let name = name.into();
+        self.fn_names.insert(name.clone());
+        self.ctx_init.declare_func(name, func);
+        self
+    }
+    /// Mark a function as deprecated, so that calling it produces a warning with `note` as the
+    /// hint, see [`Self::parse_with_diagnostics()`].
+    ///
+    /// # Examples
+    /// ```
+    /// let mut en = ensan::Engine::new();
+    /// en.deprecate_fn("strrev", "use `reverse()` instead");
+    /// let (_, warnings) = en.parse_with_diagnostics(r#"x = strrev("abc")"#).unwrap();
+    /// assert_eq!(warnings[0].message, "function `strrev` is deprecated");
+    /// assert_eq!(warnings[0].hints, ["use `reverse()` instead"]);
+    /// ```
+    pub fn deprecate_fn(&mut self, name: impl Into<String>, note: impl Into<String>) -> &mut Self {
+        self.deprecated_fns.insert(name.into(), note.into());
+        self
+    }
+    /// Register a custom function, see [`Self::register_fn()`].
+    #[must_use]
+    pub fn with_fn(mut self, name: impl Into<String>, func: FuncDef) -> Self {
+        self.register_fn(name, func);
+        self
+    }
+    /// Register a custom function from a closure and its parameter types.
+    ///
+    /// Since `hcl-rs` stores functions as plain function pointers, only closures that do not
+    /// capture anything from their environment can be used here. Use [`Self::set_var()`] to pass
+    /// runtime data into the hcl strings instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use hcl::{eval::ParamType, Value};
+    ///
+    /// let mut en = ensan::Engine::new();
+    /// en.register_closure("greet", [ParamType::String], |args| {
+    ///     Ok(Value::from(format!("hello, {}", args[0].as_str().unwrap_or_default())))
+    /// });
+    /// let body = en.parse(r#"x = greet("nya")"#).unwrap();
+    /// assert_eq!(body, ensan::parse(r#"x = "hello, nya""#).unwrap());
+    /// ```
+    pub fn register_closure(
+        &mut self,
+        name: impl Into<String>,
+        params: impl IntoIterator<Item = ParamType>,
+        func: Func,
+    ) -> &mut Self {
+        self.register_fn(name, FuncDef::new(func, params))
+    }
+    /// Register a group of custom functions, usually generated by [`crate::ensan_fn_mod`].
+    ///
+    /// See [`Self::register_fn()`].
+    ///
+    /// # Examples
+    /// ```
+    /// #[ensan::ensan_fn_mod(my_fns)]
+    /// pub mod my_mod {
+    ///     use ensan::hcl::Value;
+    ///
+    ///     #[ensan_fn]
+    ///     pub fn shout(s: &str) -> Result<Value, String> {
+    ///         Ok(s.to_uppercase().into())
+    ///     }
+    /// }
+    ///
+    /// let mut en = ensan::Engine::new();
+    /// en.register_fns(my_fns());
+    /// let body = en.parse(r#"x = shout("hai")"#).unwrap();
+    /// assert_eq!(body, ensan::parse(r#"x = "HAI""#).unwrap());
+    /// ```
+    pub fn register_fns<N: Into<String>>(
+        &mut self,
+        funcs: impl IntoIterator<Item = (N, FuncDef)>,
+    ) -> &mut Self {
+        for (name, func) in funcs {
+            self.register_fn(name, func);
+        }
+        self
+    }
+    /// Register a group of custom functions, see [`Self::register_fns()`].
+    #[must_use]
+    pub fn with_fns<N: Into<String>>(
+        mut self,
+        funcs: impl IntoIterator<Item = (N, FuncDef)>,
+    ) -> Self {
+        self.register_fns(funcs);
         self
     }
     // NOTE: since 0.1.2 we are only calling this once then we just clone `self.ctx_init`
     // everytime we want a new context. This is because `ctx.declare_func()` is actually pretty
     // expensive. According to my benchmarks using flamegraph, during execution of
-    // [`Self::parse_struct()`], 46% of the time it would be inside `init_ctx()`.
-    fn init_ctx(ctx: &mut Context) {
+    // [`Self::parse_body()`], 46% of the time it would be inside `init_ctx()`.
+    fn init_ctx(&mut self) {
         // We are going to import each function module here
-        // todo: Make even more robust thing here or we can separate loading of each module by feature flags
         #[cfg(feature = "fn-misc")]
-        crate::functions::ensan_builtin_fns(ctx);
+        self.register_fns(crate::functions::ensan_builtin_fns());
         #[cfg(feature = "fn-strings")]
-        crate::functions::string_manipulation(ctx);
+        self.register_fns(crate::functions::string_manipulation());
+        #[cfg(feature = "fn-collections")]
+        self.register_fns(crate::functions::collections());
         #[cfg(feature = "fn-encoding")]
-        crate::functions::encoding(ctx);
+        self.register_fns(crate::functions::encoding());
         #[cfg(feature = "fn-hashing")]
-        crate::functions::hashing(ctx);
+        self.register_fns(crate::functions::hashing());
         #[cfg(feature = "fn-uuid")]
-        crate::functions::uuid(ctx);
+        self.register_fns(crate::functions::uuid());
+    }
+
+    /// Collect all attributes inside `body` as nodes of the reference graph.
+    ///
+    /// With [`Self::block_lists`], blocks without labels are scoped by their index among the
+    /// blocks of the same identifier in the body, e.g. `rule.1.name`.
+    ///
+    /// Blocks with meta-arguments are collected as a single node depending on the references in
+    /// the meta-arguments, and their bodies are left for [`Self::expand()`].
+    fn collect_nodes(
+        body: &hcl::Body,
+        src: &Source<'_>,
+        addr: &mut Vec<usize>,
+        scope: &mut Vec<String>,
+        frames: &mut Vec<String>,
+        bounds: &mut Vec<usize>,
+        out: &mut Collected,
+    ) {
+        let counts = (body.blocks())
+            .filter(|block| block.labels.is_empty() && !Self::is_expanded(block))
+            .filter(|block| !(scope.is_empty() && Self::is_locals(block)))
+            .filter(|block| !Self::is_validation(block))
+            .counts_by(|block| block.identifier.as_str());
+        let mut indices = std::collections::HashMap::new();
+        for (i, structure) in body.iter().enumerate() {
+            addr.push(i);
+            match structure {
+                hcl::Structure::Attribute(attr) => {
+                    let usage = crate::graph::usage(&attr.expr);
+                    out.nodes.push(Node {
+                        addr: addr.clone(),
+                        scope: scope.clone(),
+                        key: attr.key.to_string(),
+                        kind: NodeKind::Attr,
+                        refs: usage.refs,
+                        funcs: usage.funcs,
+                        frames: frames.clone(),
+                        bounds: bounds.clone(),
+                        span: src.spans.get(addr).cloned().unwrap_or_default(),
+                    });
+                }
+                hcl::Structure::Block(block) if scope.is_empty() && Self::is_variable(block) => {
+                    Self::collect_variable_node(block, src, addr, frames, out);
+                }
+                hcl::Structure::Block(block) if Self::is_validation(block) => {
+                    Self::collect_validation_node(block, src, addr, scope, frames, bounds, out);
+                }
+                hcl::Structure::Block(block) if Self::is_expanded(block) => {
+                    Self::collect_block_node(block, src, addr, scope, frames, bounds, out);
+                }
+                hcl::Structure::Block(block) => {
+                    let old_scope_len = scope.len();
+                    bounds.push(old_scope_len);
+                    let ident = block.identifier.as_str();
+                    let count = counts.get(ident).copied().unwrap_or_default();
+                    if scope.is_empty() && Self::is_locals(block) {
+                        scope.push("local".to_owned());
+                        frames.push("locals".to_owned());
+                    } else if block.labels.is_empty() && out.block_lists {
+                        let idx: &mut usize = indices.entry(ident).or_default();
+                        if *idx == 0 {
+                            out.lists.push((scope.clone(), ident.to_owned(), count));
+                        }
+                        scope.extend([ident.to_owned(), idx.to_string()]);
+                        frames.push(format!("{ident}[{idx}]"));
+                        *idx += 1;
+                    } else {
+                        scope.push(ident.to_owned());
+                        for label in &block.labels {
+                            out.labeled.insert(scope.clone());
+                            scope.push(label.as_str().to_owned());
+                        }
+                        frames.push(Self::frame(block));
+                    }
+                    Self::collect_nodes(&block.body, src, addr, scope, frames, bounds, out);
+                    bounds.pop();
+                    frames.pop();
+                    scope.truncate(old_scope_len);
+                }
+            }
+            addr.pop();
+        }
     }
 
-    fn parse_block(&mut self, block: &mut hcl::Block) -> Res<()> {
-        let old_scope_len = self.scope.len();
+    /// Collect a block with meta-arguments as a node depending on the references in the
+    /// meta-arguments, and on the references escaping its body through `root` and `parent`, see
+    /// [`Self::expand()`].
+    ///
+    /// A `dynamic "ident"` block is collected as `ident`, the blocks it generates.
+    fn collect_block_node(
+        block: &hcl::Block,
+        src: &Source<'_>,
+        addr: &[usize],
+        scope: &[String],
+        frames: &[String],
+        bounds: &[usize],
+        out: &mut Collected,
+    ) {
+        let iterator = Self::iterator(block);
+        let (key, labels) = match (&iterator, &block.labels[..]) {
+            (Some(_), [label]) => (label.as_str().to_owned(), vec![]),
+            _ => (
+                block.identifier.to_string(),
+                (block.labels.iter())
+                    .map(|l| l.as_str().to_owned())
+                    .collect(),
+            ),
+        };
+        let keyed = (block.body.attributes()).any(|attr| attr.key.as_str() == "for_each");
+        if keyed && iterator.is_none() {
+            out.labeled
+                .insert([scope, std::slice::from_ref(&key), &labels].concat());
+        }
+        let mut usage = crate::graph::Usage::default();
+        let mut span = None;
+        let dynamic_labels = (block.body.iter().enumerate())
+            .filter(|_| iterator.is_some())
+            .filter_map(|(i, structure)| match structure {
+                hcl::Structure::Attribute(attr) if attr.key.as_str() == "labels" => Some((i, attr)),
+                _ => None,
+            });
+        for (i, attr) in Self::meta_args(block).chain(dynamic_labels) {
+            let meta = crate::graph::usage(&attr.expr);
+            // the iterator of a `dynamic` block is not a reference to another attribute
+            (usage.refs).extend(
+                meta.refs
+                    .into_iter()
+                    .filter(|r| r.first() != iterator.as_ref()),
+            );
+            usage.funcs.extend(meta.funcs);
+            span = span.or_else(|| src.spans.get(&[addr, &[i]].concat()));
+        }
+        let body = match &iterator {
+            Some(_) => Self::content(block).map(|(_, content)| &content.body),
+            None => Some(&block.body),
+        };
+        if let Some(body) = body {
+            Self::escaping_refs(body, true, &mut usage.refs);
+        }
+        out.nodes.push(Node {
+            addr: addr.to_vec(),
+            scope: scope.to_vec(),
+            key,
+            kind: NodeKind::Block(labels),
+            refs: usage.refs,
+            funcs: usage.funcs,
+            frames: [frames, &[Self::frame(block)]].concat(),
+            bounds: bounds.to_vec(),
+            span: span.cloned().unwrap_or_default(),
+        });
+    }
+
+    /// Collect a `variable` block as a node depending on the references in its `default` and in
+    /// its `validation` blocks, except for the variable itself, see [`Self::bind_variable()`].
+    fn collect_variable_node(
+        block: &hcl::Block,
+        src: &Source<'_>,
+        addr: &[usize],
+        frames: &[String],
+        out: &mut Collected,
+    ) {
+        let key = block.labels[0].as_str().to_owned();
+        let validations = (block.body.blocks())
+            .filter(|b| Self::is_validation(b))
+            .flat_map(|b| b.body.attributes());
+        let mut usage = crate::graph::Usage::default();
+        for attr in (block.body.attributes())
+            .filter(|attr| attr.key.as_str() == "default")
+            .chain(validations)
         {
-            self.scope.reserve(1 + block.labels.len());
-            self.scope.push(block.identifier.to_string());
-            self.scope
-                .extend(block.labels.iter().map(|bl| bl.to_owned().into_inner()));
-            // assumes ctx could only be inserted for same scope
-            let mut ctx = self.ctx_init.clone();
-            self.varlist.populate_hcl_ctx(&mut ctx, &self.scope);
-            for structure in &mut block.body {
-                self.parse_struct(structure, &mut ctx)?;
-            }
-        }
-        self.scope.drain(old_scope_len..);
-        Ok(())
+            let used = crate::graph::usage(&attr.expr);
+            (usage.refs).extend(
+                (used.refs.into_iter()).filter(|r| !(r.len() > 1 && r[0] == "var" && r[1] == key)),
+            );
+            usage.funcs.extend(used.funcs);
+        }
+        out.nodes.push(Node {
+            addr: addr.to_vec(),
+            scope: vec![],
+            key,
+            kind: NodeKind::Variable,
+            refs: usage.refs,
+            funcs: usage.funcs,
+            frames: [frames, &[Self::frame(block)]].concat(),
+            bounds: vec![],
+            span: src.spans.get(addr).cloned().unwrap_or_default(),
+        });
+    }
+
+    /// Collect a `validation` block as a node depending on the references in its attributes, in
+    /// the scope of the body containing it, see [`Self::validate()`].
+    fn collect_validation_node(
+        block: &hcl::Block,
+        src: &Source<'_>,
+        addr: &[usize],
+        scope: &[String],
+        frames: &[String],
+        bounds: &[usize],
+        out: &mut Collected,
+    ) {
+        let mut usage = crate::graph::Usage::default();
+        let mut span = None;
+        for (i, structure) in block.body.iter().enumerate() {
+            let hcl::Structure::Attribute(attr) = structure else {
+                continue;
+            };
+            let used = crate::graph::usage(&attr.expr);
+            usage.refs.extend(used.refs);
+            usage.funcs.extend(used.funcs);
+            if attr.key.as_str() == "condition" {
+                span = src.spans.get(&[addr, &[i]].concat());
+            }
+        }
+        out.nodes.push(Node {
+            addr: addr.to_vec(),
+            scope: scope.to_vec(),
+            key: block.identifier.to_string(),
+            kind: NodeKind::Validation,
+            refs: usage.refs,
+            funcs: usage.funcs,
+            frames: [frames, &[Self::frame(block)]].concat(),
+            bounds: bounds.to_vec(),
+            span: (span.or_else(|| src.spans.get(addr)).cloned()).unwrap_or_default(),
+        });
     }
 
-    fn parse_struct(&mut self, structure: &mut hcl::Structure, ctx: &mut Context) -> Res<()> {
+    /// Collect the references to `root`, `local` and `var` inside `body`, and to `parent` from the
+    /// attributes directly in it if `top` is set, relative to the scope containing the block of
+    /// `body`.
+    fn escaping_refs(body: &hcl::Body, top: bool, refs: &mut Vec<crate::graph::Reference>) {
+        for structure in body {
+            match structure {
+                hcl::Structure::Attribute(attr)
+                    if !(top && META_ARGS.contains(&attr.key.as_str())) =>
+                {
+                    let usage = crate::graph::usage(&attr.expr);
+                    refs.extend(
+                        usage
+                            .refs
+                            .into_iter()
+                            .filter_map(|r| match r.first()?.as_str() {
+                                "root" | "local" | "var" => Some(r),
+                                "parent" if top => Some(r[1..].to_vec()),
+                                _ => None,
+                            }),
+                    );
+                }
+                hcl::Structure::Attribute(_) => {}
+                // the attributes of `validation` blocks are evaluated in the scope containing them
+                hcl::Structure::Block(block) => {
+                    let top = top && Self::is_validation(block);
+                    Self::escaping_refs(&block.body, top, refs);
+                }
+            }
+        }
+    }
+
+    /// The `content` block of a `dynamic` block along with its index in the body.
+    fn content(block: &hcl::Block) -> Option<(usize, &hcl::Block)> {
+        (block.body.iter().enumerate()).find_map(|(i, structure)| match structure {
+            hcl::Structure::Block(b) if b.identifier.as_str() == "content" => Some((i, b)),
+            _ => None,
+        })
+    }
+
+    /// Whether the block is a `locals` block, whose attributes are in the `local` scope when
+    /// written at the top level of the document.
+    fn is_locals(block: &hcl::Block) -> bool {
+        block.identifier.as_str() == "locals" && block.labels.is_empty()
+    }
+
+    /// Whether the block is a `variable "name"` block, which is bound to `var.<name>` when written
+    /// at the top level of the document, see [`Engine::bind_variable()`].
+    fn is_variable(block: &hcl::Block) -> bool {
+        block.identifier.as_str() == "variable" && block.labels.len() == 1
+    }
+
+    /// Whether the block is a `validation` block, see [`Engine::validate()`].
+    fn is_validation(block: &hcl::Block) -> bool {
+        block.identifier.as_str() == "validation" && block.labels.is_empty()
+    }
+
+    /// The block as written, e.g. `blk "one" "two"`.
+    fn frame(block: &hcl::Block) -> String {
+        std::iter::once(block.identifier.to_string())
+            .chain(block.labels.iter().map(|bl| match bl {
+                hcl::BlockLabel::Identifier(id) => id.to_string(),
+                hcl::BlockLabel::String(s) => format!("{s:?}"),
+            }))
+            .join(" ")
+    }
+
+    /// Whether the block has meta-arguments or is a `dynamic` block, see [`Self::expand()`].
+    fn is_expanded(block: &hcl::Block) -> bool {
+        Self::iterator(block).is_some() || Self::meta_args(block).next().is_some()
+    }
+
+    /// The name of the iterator variable if the block is a `dynamic` block, which is either set
+    /// with `iterator = <name>` or the label of the block.
+    fn iterator(block: &hcl::Block) -> Option<String> {
+        let [label] = &block.labels[..] else {
+            return None;
+        };
+        if block.identifier.as_str() != "dynamic" {
+            return None;
+        }
+        let iterator = (block.body.attributes()).find_map(|attr| match &attr.expr {
+            hcl::Expression::Variable(var) if attr.key.as_str() == "iterator" => {
+                Some(var.to_string())
+            }
+            _ => None,
+        });
+        Some(iterator.unwrap_or_else(|| label.as_str().to_owned()))
+    }
+
+    /// Whether the structure is one of the [`META_ARGS`], see [`Self::expand()`].
+    fn is_meta_arg(structure: &hcl::Structure) -> bool {
         match structure {
-            hcl::Structure::Attribute(attr) => {
-                let val = attr.expr.evaluate(ctx)?;
-                self.varlist
-                    .set(&self.scope, attr.key.to_string(), val.clone());
-                // notice we are defining a new var in the same scope
-                ctx.declare_var(attr.key.clone(), val.clone());
-                *attr.expr.borrow_mut() = val.into(); // NOTE: this is where we need &mut structure
-            }
-            hcl::Structure::Block(block) => {
-                self.parse_block(block)?;
-                let vs: VarScopes = self
-                    .varlist
-                    .list_in_scope_ref(&self.scope)
-                    .filter(|v| matches!(v, VarScope::Scope(k, _) if k == block.identifier()))
-                    .cloned()
-                    .collect_vec()
-                    .into();
-                vs.populate_hcl_ctx(ctx, &[] as &[&str]);
+            hcl::Structure::Attribute(attr) => META_ARGS.contains(&attr.key.as_str()),
+            hcl::Structure::Block(_) => false,
+        }
+    }
+
+    /// The meta-arguments of the block along with their indices in the body.
+    fn meta_args(block: &hcl::Block) -> impl Iterator<Item = (usize, &hcl::Attribute)> {
+        (block.body.iter().enumerate()).filter_map(|(i, structure)| match structure {
+            hcl::Structure::Attribute(attr) if Self::is_meta_arg(structure) => Some((i, attr)),
+            _ => None,
+        })
+    }
+
+    /// Convert the parsed `body` of the string at index `file` in `src` into `out`, collecting the
+    /// spans of the attribute expressions and block identifiers, and replacing `include "path" {}`
+    /// (or `import`) blocks with the structures of the files at the paths.
+    ///
+    /// Relative paths are resolved against the directory of the including file, and the files must
+    /// be inside [`Self::include_dir`]. The bodies of the blocks must be empty. `stack` is the
+    /// chain of files being included, for detecting include cycles.
+    fn load(
+        src: &mut Source<'_>,
+        body: hcl::edit::structure::Body,
+        file: usize,
+        addr: &mut Vec<usize>,
+        out: &mut hcl::Body,
+        stack: &mut Vec<std::path::PathBuf>,
+    ) -> Res<()> {
+        use hcl::edit::structure::{BlockLabel, Structure};
+        for structure in body {
+            addr.push(out.0.len());
+            src.origins.insert(addr.clone(), file);
+            match structure {
+                Structure::Attribute(attr) => {
+                    src.spans
+                        .insert(addr.clone(), attr.value.span().unwrap_or_default());
+                    out.0.push(hcl::Attribute::from(attr).into());
+                }
+                Structure::Block(block) => match &block.labels[..] {
+                    [BlockLabel::String(path)]
+                        if ["include", "import"].contains(&block.ident.as_str()) =>
+                    {
+                        addr.pop();
+                        let location = src.files.get(file).map(|(f, text)| {
+                            let span = block.ident.span().unwrap_or_default();
+                            Location::new(f.as_deref(), text, span, Self::edit_frame(&block))
+                        });
+                        let res = if block.body.is_empty() {
+                            Self::include(src, path.as_str(), file, addr, out, stack)
+                        } else {
+                            Err(crate::Error::Include {
+                                path: path.to_string(),
+                                message: "the body of the block must be empty".into(),
+                            })
+                        };
+                        res.map_err(|e| match location {
+                            Some(location) if e.location().is_none() => e.at(location),
+                            _ => e,
+                        })?;
+                        continue;
+                    }
+                    _ => {
+                        src.spans
+                            .insert(addr.clone(), block.ident.span().unwrap_or_default());
+                        let mut block = block;
+                        let body = std::mem::take(&mut block.body);
+                        let mut block = hcl::Block::from(block);
+                        Self::load(src, body, file, addr, &mut block.body, stack)?;
+                        out.0.push(block.into());
+                    }
+                },
             }
+            addr.pop();
         }
         Ok(())
     }
 
+    /// Load the file at `path` included from the string at index `file` in `src`, see
+    /// [`Self::load()`].
+    fn include(
+        src: &mut Source<'_>,
+        path: &str,
+        file: usize,
+        addr: &mut Vec<usize>,
+        out: &mut hcl::Body,
+        stack: &mut Vec<std::path::PathBuf>,
+    ) -> Res<()> {
+        let denied = |message: String| crate::Error::Include {
+            path: path.to_owned(),
+            message,
+        };
+        let Some(include_dir) = &src.include_dir else {
+            return Err(denied(
+                "includes are disabled, see `Engine::include_dir`".into(),
+            ));
+        };
+        let including = src.files.get(file).and_then(|(f, _)| f.as_deref());
+        let path = (including.and_then(|f| std::path::Path::new(f).parent()))
+            .map_or_else(|| path.into(), |dir| dir.join(path));
+        let canonical = std::fs::canonicalize(&path).map_err(|source| crate::Error::Io {
+            path: path.clone(),
+            source,
+        })?;
+        if !canonical.starts_with(include_dir) {
+            let dir = include_dir.display();
+            return Err(denied(format!("the file is outside of `{dir}`")));
+        }
+        if let Some(pos) = stack.iter().position(|p| *p == canonical) {
+            let chain = (stack[pos..].iter().chain([&canonical]))
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(crate::Error::IncludeCycle(chain));
+        }
+        let text = std::fs::read_to_string(&path).map_err(|source| crate::Error::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let name = path.display().to_string();
+        let parsed = Self::parse_edit(Some(&name), &text)?;
+        src.files.push((Some(name.into()), text.into()));
+        stack.push(canonical);
+        let res = Self::load(src, parsed, src.files.len() - 1, addr, out, stack);
+        stack.pop();
+        res
+    }
+
+    /// The block as written, e.g. `include "a.hcl"`, see [`Self::frame()`].
+    fn edit_frame(block: &hcl::edit::structure::Block) -> String {
+        let labels = block.labels.iter().map(|label| match label {
+            hcl::edit::structure::BlockLabel::Ident(id) => id.to_string(),
+            hcl::edit::structure::BlockLabel::String(s) => format!("{:?}", s.as_str()),
+        });
+        std::iter::once(block.ident.to_string())
+            .chain(labels)
+            .join(" ")
+    }
+
+    /// Parse the string into an [`hcl::edit::structure::Body`], locating syntax errors.
+    fn parse_edit(file: Option<&str>, text: &str) -> Res<hcl::edit::structure::Body> {
+        text.parse().map_err(|e| {
+            let e: hcl::edit::parser::Error = e;
+            let loc = e.location();
+            let location = Location {
+                file: file.map(str::to_owned),
+                span: loc.offset()..loc.offset(),
+                line: loc.line(),
+                column: loc.column(),
+                path: String::new(),
+            };
+            crate::Error::from(hcl::Error::from(e)).at(location)
+        })
+    }
+
+    /// Find the structure at `addr`, which is a list of indices into the nested bodies.
+    fn at_mut<'b>(body: &'b mut hcl::Body, addr: &[usize]) -> Option<&'b mut hcl::Structure> {
+        let (last, parent) = addr.split_last()?;
+        Self::body_at_mut(body, parent)?.0.get_mut(*last)
+    }
+
+    /// Find the body of the block at `addr`, or `body` itself if `addr` is empty.
+    fn body_at_mut<'b>(body: &'b mut hcl::Body, addr: &[usize]) -> Option<&'b mut hcl::Body> {
+        let [first, rest @ ..] = addr else {
+            return Some(body);
+        };
+        match body.0.get_mut(*first)? {
+            hcl::Structure::Block(block) => Self::body_at_mut(&mut block.body, rest),
+            hcl::Structure::Attribute(_) => None,
+        }
+    }
+
+    /// Find the non-fatal problems with the attributes: redefined attributes, attributes
+    /// shadowing [`Self::inputs`] and calls to deprecated functions.
+    fn lint(
+        &self,
+        nodes: &[Node],
+        paths: &BTreeMap<Vec<&str>, Vec<usize>>,
+        src: &Source<'_>,
+    ) -> Vec<Diagnostic> {
+        let warn = |node: &Node, message: String, hints: Vec<String>| Diagnostic {
+            severity: Severity::Warning,
+            message,
+            location: Some(node.location(src)),
+            hints,
+        };
+        let mut warnings = vec![];
+        // these are errors in strict mode instead
+        for (path, ids) in paths.iter().filter(|_| !self.strict) {
+            // locating is linear in the length of the string, so only done for duplicates
+            let [first, rest @ ..] = &ids[..] else {
+                continue;
+            };
+            if rest.is_empty() {
+                continue;
+            }
+            let first = nodes[*first].location(src);
+            for &i in rest {
+                warnings.push(warn(
+                    &nodes[i],
+                    format!(
+                        "`{}` is defined more than once, the last value wins",
+                        path.join(".")
+                    ),
+                    vec![format!("previously defined at {first}")],
+                ));
+            }
+        }
+        for node in nodes {
+            let shadowed = node.kind == NodeKind::Attr
+                && (self.inputs.list_in_scope_ref(&node.scope))
+                    .any(|v| matches!(v, VarScope::Var(k, _) if *k == node.key));
+            if shadowed {
+                warnings.push(warn(
+                    node,
+                    format!(
+                        "`{}` shadows a variable set with `Engine::set_var()`",
+                        node.path().join(".")
+                    ),
+                    vec![],
+                ));
+            }
+            for func in node.funcs.iter().unique() {
+                if let Some(note) = self.deprecated_fns.get(func) {
+                    warnings.push(warn(
+                        node,
+                        format!("function `{func}` is deprecated"),
+                        vec![note.clone()],
+                    ));
+                }
+            }
+        }
+        warnings.sort_by_key(|w| (w.location.as_ref()).map(|l| (l.file.clone(), l.span.start)));
+        warnings
+    }
+
+    /// Find the pairs of attributes `(earlier, later)` that are defined in the same scope with the
+    /// same path, or where the path of one is a prefix of the other, i.e. an attribute conflicting
+    /// with a block.
+    fn conflicts(paths: &BTreeMap<Vec<&str>, Vec<usize>>) -> Vec<(usize, usize)> {
+        let mut conflicts = vec![];
+        for (path, ids) in paths {
+            conflicts.extend(ids.iter().skip(1).map(|&i| (ids[0], i)));
+            (paths.range(path.clone()..).skip(1))
+                .take_while(|(other, _)| other.starts_with(path))
+                .flat_map(|(_, others)| others)
+                .for_each(|&j| conflicts.push((ids[0].min(j), ids[0].max(j))));
+        }
+        conflicts.sort_by_key(|&(_, i)| i);
+        conflicts
+    }
+
+    /// Report the conflicting attributes in [`Self::strict`] mode, see [`Self::conflicts()`].
+    fn redefinitions(
+        &self,
+        nodes: &[Node],
+        paths: &BTreeMap<Vec<&str>, Vec<usize>>,
+        src: &Source<'_>,
+    ) -> Res<Vec<crate::Error>> {
+        let mut errors = vec![];
+        if !self.strict {
+            return Ok(errors);
+        }
+        for (prev, i) in Self::conflicts(paths) {
+            let err = crate::Error::Redefined {
+                path: nodes[i].path().join("."),
+                previous: nodes[prev].location(src),
+            }
+            .at(nodes[i].location(src));
+            if !self.collect_errors {
+                return Err(err);
+            }
+            errors.push(err);
+        }
+        Ok(errors)
+    }
+
+    /// The indices of the nodes by their paths, e.g. `["blk", "a", "foo"]`.
+    ///
+    /// `validation` blocks are left out since they cannot be referenced and may be repeated.
+    fn paths(nodes: &[Node]) -> BTreeMap<Vec<&str>, Vec<usize>> {
+        let mut paths: BTreeMap<Vec<&str>, Vec<usize>> = BTreeMap::new();
+        for (i, node) in (nodes.iter().enumerate()).filter(|(_, n)| n.kind != NodeKind::Validation)
+        {
+            paths.entry(node.path().collect_vec()).or_default().push(i);
+        }
+        paths
+    }
+
+    /// Whether an attribute or a block named `name` is in `scope`.
+    fn defines(paths: &BTreeMap<Vec<&str>, Vec<usize>>, scope: &[String], name: &str) -> bool {
+        let path = scope.iter().map(String::as_str).chain([name]).collect_vec();
+        (paths.range(path.clone()..).next()).is_some_and(|(p, _)| p.starts_with(&path))
+    }
+
+    /// Find the dependencies of each node, see [`Self::parse_body()`].
+    fn dependencies(nodes: &[Node], paths: &BTreeMap<Vec<&str>, Vec<usize>>) -> Vec<Vec<usize>> {
+        (nodes.iter())
+            .map(|node| {
+                let mut deps = vec![];
+                for r in &node.refs {
+                    let full = node.resolve(r, |name| Self::defines(paths, &node.scope, name));
+                    // attributes that are a prefix of the reference, e.g. `obj` for `obj.key`
+                    (1..full.len())
+                        .filter_map(|len| paths.get(&full[..len]))
+                        .for_each(|ids| deps.extend(ids));
+                    // attributes under the reference, e.g. `blk.a.foo` for `blk.a`
+                    (paths.range(full.clone()..))
+                        .take_while(|(path, _)| path.starts_with(&full))
+                        .for_each(|(_, ids)| deps.extend(ids));
+                }
+                deps
+            })
+            .collect_vec()
+    }
+
+    /// Collect the nodes in `body`, see [`Self::collect_nodes()`], and register the lists of blocks
+    /// found in it in [`Self::varlist`].
+    ///
+    /// Returns the nodes along with the paths of the labeled blocks.
+    fn collect(
+        &mut self,
+        body: &hcl::Body,
+        origin: &Origin,
+        src: &Source<'_>,
+    ) -> (Vec<Node>, BTreeSet<Vec<String>>) {
+        let mut collected = Collected {
+            block_lists: self.block_lists,
+            ..Collected::default()
+        };
+        Self::collect_nodes(
+            body,
+            src,
+            &mut origin.addr.clone(),
+            &mut self.scope.clone(),
+            &mut origin.frames.clone(),
+            &mut origin.bounds.clone(),
+            &mut collected,
+        );
+        let Collected {
+            mut nodes,
+            lists,
+            labeled,
+            ..
+        } = collected;
+        if origin.instance {
+            nodes.retain(|node| match &node.addr[origin.addr.len()..] {
+                [i] => !body.0.get(*i).is_some_and(Self::is_meta_arg),
+                _ => true,
+            });
+        }
+        for (scope, key, len) in lists {
+            self.varlist.set_list(&scope, key.clone(), len);
+            self.touch(&scope, &key, None);
+        }
+        (nodes, labeled)
+    }
+
+    /// Evaluate all attributes in `body`, in the order of their dependencies.
+    ///
+    /// If [`Self::collect_errors`] is set, the errors are returned instead, and the attributes that
+    /// failed or depend on a failed one are left unevaluated.
+    ///
+    /// A reference `foo.bar` made from an attribute inside scope `blk.a` depends on every
+    /// attribute whose path (`blk.a.foo.bar.*`) either starts with or is a prefix of
+    /// `blk.a.foo.bar`.
+    fn parse_body(
+        &mut self,
+        body: &mut hcl::Body,
+        origin: &Origin,
+        src: &Source<'_>,
+        warnings: &mut Vec<Diagnostic>,
+    ) -> Res<Vec<crate::Error>> {
+        let (nodes, labeled) = self.collect(body, origin, src);
+        let paths = Self::paths(&nodes);
+        warnings.extend(self.lint(&nodes, &paths, src));
+        let mut errors = self.redefinitions(&nodes, &paths, src)?;
+        let mut deps = Self::dependencies(&nodes, &paths);
+
+        // attributes that failed, or that depend on one that failed
+        let mut failed = vec![false; nodes.len()];
+        let order = loop {
+            match crate::graph::toposort(&deps) {
+                Ok(order) => break order,
+                Err(cycle) => {
+                    let err = crate::Error::Cycle(
+                        (cycle.iter()).map(|&i| nodes[i].path().join(".")).collect(),
+                    )
+                    .at(nodes[cycle[0]].location(src));
+                    if !self.collect_errors {
+                        return Err(err);
+                    }
+                    errors.push(err);
+                    // break the cycle so that the other attributes can still be sorted
+                    for i in cycle {
+                        failed[i] = true;
+                        deps[i].clear();
+                    }
+                }
+            }
+        };
+        let old_scope = self.scope.clone();
+        // the instances of the expanded blocks by their addresses relative to `body`
+        let mut expanded = vec![];
+        for i in order {
+            if failed[i] || deps[i].iter().any(|&dep| failed[dep]) {
+                failed[i] = true;
+                continue;
+            }
+            let node = &nodes[i];
+            let addr = &node.addr[origin.addr.len()..];
+            let res = match Self::at_mut(body, addr) {
+                Some(hcl::Structure::Attribute(attr)) => {
+                    (self.evaluate(&mut attr.expr, node, &labeled))
+                        .map_err(|e| e.at(node.location(src)))
+                }
+                Some(hcl::Structure::Block(block)) if node.kind == NodeKind::Variable => {
+                    (self.bind_variable(block, node, &labeled, src)).map(|()| Value::Null)
+                }
+                Some(hcl::Structure::Block(block)) if node.kind == NodeKind::Validation => {
+                    (self.validate(block, node, &labeled, src)).map(|()| {
+                        expanded.push((addr.to_vec(), vec![]));
+                        Value::Null
+                    })
+                }
+                Some(hcl::Structure::Block(block)) => {
+                    (self.expand(block, node, &labeled, src, warnings)).map(|(instances, errs)| {
+                        errors.extend(errs);
+                        expanded.push((addr.to_vec(), instances));
+                        Value::Null
+                    })
+                }
+                None => continue,
+            };
+            let val = match res {
+                Ok(val) => val,
+                Err(err) if !self.collect_errors => {
+                    self.scope = old_scope;
+                    return Err(err);
+                }
+                Err(err) => {
+                    errors.push(err);
+                    failed[i] = true;
+                    continue;
+                }
+            };
+            if let Some(hcl::Structure::Attribute(attr)) = Self::at_mut(body, addr) {
+                self.declare(&node.scope, &node.key, val.clone());
+                *attr.expr.borrow_mut() = val.into(); // NOTE: this is where we need &mut body
+            }
+        }
+        self.scope = old_scope;
+        Self::replace_blocks(body, expanded);
+        Ok(errors)
+    }
+
+    /// Replace the blocks at the addresses with their instances, see [`Self::expand()`].
+    fn replace_blocks(body: &mut hcl::Body, mut expanded: Vec<(Vec<usize>, Vec<hcl::Structure>)>) {
+        // replace the blocks from the last one so that the addresses of the others stay valid
+        expanded.sort_by(|(a, _), (b, _)| b.cmp(a));
+        for (addr, instances) in expanded {
+            if let Some((&last, parent)) = addr.split_last() {
+                if let Some(parent) = Self::body_at_mut(body, parent) {
+                    parent.0.splice(last..=last, instances);
+                }
+            }
+        }
+    }
+
+    /// Evaluate `expr` in the scope of `node`, with the splat expressions on labeled blocks
+    /// rewritten, see [`crate::graph::expand_splats()`].
+    ///
+    /// Besides the attributes in the scope, these built-in objects can be referenced:
+    /// - `self`: the block containing the attribute
+    /// - `parent`: the block containing that block, unless the attribute is at the top level
+    /// - `root`: the whole document
+    /// - `local`: the attributes of the `locals` blocks at the top level of the document
+    /// - `var`: the values of the `variable` blocks, see [`Self::bind_variable()`]
+    ///
+    /// The attributes in the scope take precedence over them.
+    fn evaluate(
+        &mut self,
+        expr: &mut hcl::Expression,
+        node: &Node,
+        labeled: &BTreeSet<Vec<String>>,
+    ) -> Res<Value> {
+        if self.contexts.is_empty() {
+            self.contexts.push(BTreeMap::new());
+        }
+        let last = self.contexts.len() - 1;
+        let scope_ctx = (self.contexts[last].entry(node.scope.clone()))
+            .or_insert_with(|| ScopeCtx::new(&self.ctx_init, &self.varlist, &node.scope));
+        scope_ctx.refresh(&self.varlist, &node.scope);
+        if !labeled.is_empty() {
+            let names = &scope_ctx.names;
+            crate::graph::expand_splats(expr, &|r| {
+                let path = node.resolve(r, |name| names.contains(name));
+                labeled.contains(&path.into_iter().map(str::to_owned).collect_vec())
+            });
+        }
+        // only built when used, since `root` is the whole document
+        let used = |name: &str| {
+            node.refs
+                .iter()
+                .any(|r| r.first().is_some_and(|f| f == name))
+        };
+        let (local, var) = (["local".to_owned()], ["var".to_owned()]);
+        let parent = node.bounds.last().map(|&len| &node.scope[..len]);
+        let builtins = [
+            ("root", Some(&[][..])),
+            ("local", Some(&local[..])),
+            ("var", Some(&var[..])),
+            ("self", Some(&node.scope[..])),
+            ("parent", parent),
+        ];
+        for (name, path) in builtins {
+            let Some(path) = path.filter(|_| used(name) && !scope_ctx.names.contains(name)) else {
+                continue;
+            };
+            let object = (self.objects.entry(path.to_vec()))
+                .or_insert_with(|| self.varlist.scope_to_hcl_value(path));
+            scope_ctx
+                .ctx
+                .declare_var(name, Self::referenced(object, name, &node.refs));
+        }
+        let res = expr.evaluate(&scope_ctx.ctx);
+        self.scope.clone_from(&node.scope);
+        res.map_err(|e| self.suggest(e, node))
+    }
+
+    /// Keep the cached contexts up to date after `key` in `scope` of [`Self::varlist`] is set to
+    /// `value`, or is changed otherwise if `None`, see [`Self::evaluate()`].
+    fn touch(&mut self, scope: &[String], key: &str, value: Option<&Value>) {
+        for contexts in &mut self.contexts {
+            for len in 0..=scope.len() {
+                let Some(scope_ctx) = contexts.get_mut(&scope[..len]) else {
+                    continue;
+                };
+                let name = scope.get(len).map_or(key, String::as_str);
+                scope_ctx.names.insert(name.to_owned());
+                if let Some(value) = value.filter(|_| len == scope.len()) {
+                    scope_ctx.ctx.declare_var(key, value.clone());
+                    scope_ctx.stale.remove(key);
+                } else {
+                    scope_ctx.stale.insert(name.to_owned());
+                }
+            }
+        }
+        // the objects containing the variable are updated in place, since `root` is the whole
+        // document, and the ones inside it are dropped
+        let mut value = value.cloned();
+        for len in 0..=scope.len() {
+            let Some(object) = self.objects.get_mut(&scope[..len]) else {
+                continue;
+            };
+            let value = value.get_or_insert_with(|| {
+                (self.varlist.list_in_scope_ref(scope))
+                    .filter_map(|v| Some(v.to_hcl_var()).filter(|(k, _)| k == key))
+                    .last()
+                    .map_or(Value::Null, |(_, v)| v)
+            });
+            if !Self::assign(object, &scope[len..], key, value) {
+                self.objects.remove(&scope[..len]);
+            }
+        }
+        let path = [scope, &[key.to_owned()]].concat();
+        let inner = (self.objects.range(path.clone()..))
+            .map(|(p, _)| p)
+            .take_while(|p| p.starts_with(&path))
+            .cloned()
+            .collect_vec();
+        for p in inner {
+            self.objects.remove(&p);
+        }
+    }
+
+    /// Set `key` at `path` inside `object` to `value`, creating the missing objects along the way
+    /// like [`VarScopes::scope_mut()`].
+    ///
+    /// Returns false if `path` does not lead to an object.
+    fn assign(object: &mut Value, path: &[String], key: &str, value: &Value) -> bool {
+        let mut cur = object;
+        for seg in path {
+            cur = match cur {
+                Value::Object(attrs) => {
+                    (attrs.entry(seg.clone())).or_insert_with(|| Value::Object(hcl::Map::new()))
+                }
+                Value::Array(items) => match seg.parse().ok().and_then(|i: usize| items.get_mut(i))
+                {
+                    Some(item) => item,
+                    None => return false,
+                },
+                _ => return false,
+            };
+        }
+        let Value::Object(attrs) = cur else {
+            return false;
+        };
+        attrs.insert(key.to_owned(), value.clone());
+        true
+    }
+
+    /// The part of the built-in `object` named `name` that is referenced in `refs`, e.g. only `z`
+    /// of `root` for `root.z`, so that the whole document is not copied for every attribute.
+    fn referenced(object: &Value, name: &str, refs: &[crate::graph::Reference]) -> Value {
+        let Value::Object(attrs) = object else {
+            return object.clone();
+        };
+        let mut out = hcl::Map::new();
+        for r in refs.iter().filter(|r| r.first().is_some_and(|f| f == name)) {
+            // the object itself is used, e.g. `keys(root)` or `root[name]`
+            let Some(key) = r.get(1) else {
+                return object.clone();
+            };
+            if let Some(v) = attrs.get(key) {
+                out.insert(key.clone(), v.clone());
+            }
+        }
+        Value::Object(out)
+    }
+
+    /// Set `key` in `scope` of [`Self::varlist`] to `value`, see [`VarScopes::set()`].
+    fn declare(&mut self, scope: &[String], key: &str, value: Value) {
+        self.touch(scope, key, Some(&value));
+        self.varlist.set(scope, key.to_owned(), value);
+    }
+
+    /// Create the scope at `path` in [`Self::varlist`] if it does not exist, see
+    /// [`VarScopes::scope_mut()`].
+    fn create_scope(&mut self, path: &[String]) {
+        self.varlist.scope_mut(path);
+        if let Some((key, scope)) = path.split_last() {
+            self.touch(scope, key, None);
+        }
+    }
+
+    /// Drop the cached contexts, see [`Self::evaluate()`].
+    fn forget_contexts(&mut self) {
+        self.contexts.clear();
+        self.objects.clear();
+    }
+
+    /// Bind the value of a `variable "name"` block to `var.<name>`.
+    ///
+    /// The value is the one set with [`Self::set_var()`] as `var.<name>`, otherwise the `default`
+    /// of the block, which is evaluated in place either way. It is then checked against the `type`
+    /// of the block, if any, see [`crate::types::Type::convert()`], and against the `validation`
+    /// blocks in it, which are then left out of the output.
+    fn bind_variable(
+        &mut self,
+        block: &mut hcl::Block,
+        node: &Node,
+        labeled: &BTreeSet<Vec<String>>,
+        src: &Source<'_>,
+    ) -> Res<()> {
+        let invalid = |message: String| crate::Error::Variable {
+            name: node.key.clone(),
+            message,
+        };
+        let input = (self.inputs.list_in_scope_ref(&["var"])).find_map(|v| match v {
+            VarScope::Var(k, v) if *k == node.key => Some(v.clone()),
+            _ => None,
+        });
+        let mut value = input.map(|v| (v, node.location(src)));
+        let mut ty = None;
+        for (i, structure) in block.body.iter_mut().enumerate() {
+            let hcl::Structure::Attribute(attr) = structure else {
+                continue;
+            };
+            let location = Self::locate_in(node, i, attr, src);
+            match attr.key.as_str() {
+                "default" => {
+                    let default = (self.evaluate(&mut attr.expr, node, labeled))
+                        .map_err(|e| e.at(location.clone()))?;
+                    *attr.expr.borrow_mut() = default.clone().into();
+                    value = value.or(Some((default, location)));
+                }
+                "type" => {
+                    let parsed = crate::types::Type::parse(&attr.expr);
+                    ty = Some(parsed.map_err(|m| invalid(m).at(location))?);
+                }
+                _ => {}
+            }
+        }
+        let Some((mut value, location)) = value else {
+            let message = "no value was set and there is no default".to_owned();
+            return Err(invalid(message).at(node.location(src)));
+        };
+        if let Some(ty) = ty {
+            value = ty.convert(value).map_err(|m| invalid(m).at(location))?;
+        }
+        let scope = ["var".to_owned()];
+        self.varlist.remove(&scope, &node.key);
+        self.declare(&scope, &node.key, value);
+        for (i, structure) in block.body.iter().enumerate() {
+            let hcl::Structure::Block(validation) = structure else {
+                continue;
+            };
+            if Self::is_validation(validation) {
+                let node = Node {
+                    addr: [&node.addr[..], &[i]].concat(),
+                    frames: [&node.frames[..], &[Self::frame(validation)]].concat(),
+                    ..node.clone()
+                };
+                self.validate(validation, &node, labeled, src)?;
+            }
+        }
+        (block.body.0).retain(|s| !matches!(s, hcl::Structure::Block(b) if Self::is_validation(b)));
+        Ok(())
+    }
+
+    /// Check a `validation { condition = ..., error_message = ... }` block, whose `node` is in
+    /// the scope containing the block.
+    ///
+    /// The `condition` must evaluate to a bool. If it is false, the `error_message` is evaluated
+    /// and returned as [`crate::Error::Validation`]. `validation` blocks are left out of the
+    /// output.
+    fn validate(
+        &mut self,
+        block: &hcl::Block,
+        node: &Node,
+        labeled: &BTreeSet<Vec<String>>,
+        src: &Source<'_>,
+    ) -> Res<()> {
+        let attr = |key: &str| {
+            (block.body.iter().enumerate()).find_map(|(i, structure)| match structure {
+                hcl::Structure::Attribute(attr) if attr.key.as_str() == key => Some((i, attr)),
+                _ => None,
+            })
+        };
+        let Some((i, condition)) = attr("condition") else {
+            let err = ErrorKind::Message("`validation` blocks require `condition`".into());
+            return Err(crate::Error::from(hcl::eval::Error::from(err)).at(node.location(src)));
+        };
+        let location = Self::locate_in(node, i, condition, src);
+        let val = (self.evaluate(&mut condition.expr.clone(), node, labeled))
+            .map_err(|e| e.at(location.clone()))?;
+        match val {
+            Value::Bool(true) => Ok(()),
+            Value::Bool(false) => {
+                let message = match attr("error_message") {
+                    Some((j, msg)) => match self.evaluate(&mut msg.expr.clone(), node, labeled) {
+                        Ok(Value::String(message)) => message,
+                        Ok(val) => {
+                            let location = Self::locate_in(node, j, msg, src);
+                            return Err(unexpected(val, "a string", location));
+                        }
+                        Err(e) => return Err(e.at(Self::locate_in(node, j, msg, src))),
+                    },
+                    None => format!("validation failed: `{}`", condition.expr),
+                };
+                Err(crate::Error::Validation { message }.at(location))
+            }
+            val => Err(unexpected(val, "a bool", location)),
+        }
+    }
+
+    /// Expand a block with meta-arguments into its instances, evaluating the body of each.
+    ///
+    /// - `for_each = <object or list of strings>` creates an instance for each key, with
+    ///   `each.key` and `each.value` in scope. The key is appended to the labels of the block and
+    ///   the instance is registered under it, e.g. `svc.a` for `svc { for_each = { a = 1 } }`.
+    /// - `count = <number>` creates that many instances, with `count.index` in scope. They are
+    ///   registered as a list like repeated blocks, e.g. `svc[0]`.
+    /// - `_enabled = <bool>` removes the block along with its instances if false, before evaluating
+    ///   anything else in it.
+    /// - `dynamic "ident" { for_each = ..., content { ... } }` creates an `ident` block from
+    ///   `content` for each item, with the iterator (see [`Self::iterator()`]) in scope as
+    ///   `ident.key` and `ident.value`, where the key of a list item is its index. The labels of
+    ///   the blocks can be set with `labels = [...]`, otherwise the blocks are registered as a
+    ///   list.
+    ///
+    /// Returns the instances along with the errors collected from their bodies.
+    fn expand(
+        &mut self,
+        block: &hcl::Block,
+        node: &Node,
+        labeled: &BTreeSet<Vec<String>>,
+        src: &Source<'_>,
+        warnings: &mut Vec<Diagnostic>,
+    ) -> Res<(Vec<hcl::Structure>, Vec<crate::Error>)> {
+        // the iterator variables are declared in `ctx_init` so that nested blocks can see them
+        let (ctx_init, depth) = (self.ctx_init.clone(), self.contexts.len());
+        let res = self.instantiate(block, node, labeled, src, warnings);
+        self.ctx_init = ctx_init;
+        self.contexts.truncate(depth);
+        res
+    }
+
+    /// Create and evaluate the instances of a block, see [`Self::expand()`].
+    fn instantiate(
+        &mut self,
+        block: &hcl::Block,
+        node: &Node,
+        labeled: &BTreeSet<Vec<String>>,
+        src: &Source<'_>,
+        warnings: &mut Vec<Diagnostic>,
+    ) -> Res<(Vec<hcl::Structure>, Vec<crate::Error>)> {
+        let Some(items) = self.iterations(block, node, labeled, src)? else {
+            return Ok((vec![], vec![]));
+        };
+        let keyed = (block.body.attributes()).any(|attr| attr.key.as_str() == "for_each");
+        let counted = (block.body.attributes()).any(|attr| attr.key.as_str() == "count");
+        let dynamic = Self::iterator(block);
+        let labels = (block.body.iter().enumerate()).find_map(|(i, structure)| match structure {
+            hcl::Structure::Attribute(attr) if attr.key.as_str() == "labels" => Some((i, attr)),
+            _ => None,
+        });
+        let labels = labels.filter(|_| dynamic.is_some());
+        let iterator =
+            (dynamic.clone()).unwrap_or_else(|| if keyed { "each" } else { "count" }.to_owned());
+        let (template, addr) = if dynamic.is_some() {
+            let (i, content) = Self::content(block).unzip();
+            let template = hcl::Block {
+                identifier: hcl::Identifier::unchecked(&node.key),
+                labels: vec![],
+                body: content.map(|b| b.body.clone()).unwrap_or_default(),
+            };
+            (template, [&node.addr[..], &Vec::from_iter(i)].concat())
+        } else {
+            (block.clone(), node.addr.clone())
+        };
+
+        let path = node.path().map(str::to_owned).collect_vec();
+        // a block with only `_enabled` is a single instance in place of the block
+        let single = dynamic.is_none() && !keyed && !counted;
+        let indexed = if dynamic.is_some() {
+            labels.is_none()
+        } else {
+            counted
+        };
+        if indexed {
+            if let Some((key, parent)) = path.split_last() {
+                self.varlist.set_list(parent, key.clone(), items.len());
+                self.touch(parent, key, None);
+            }
+        } else if dynamic.is_none() {
+            self.create_scope(&path);
+        }
+        let (mut out, mut errors) = (vec![], vec![]);
+        let depth = self.contexts.len();
+        for (i, (key, value)) in items.into_iter().enumerate() {
+            if !single {
+                self.ctx_init.declare_var(iterator.as_str(), value);
+                // the contexts cached so far lack the iterator
+                self.contexts.truncate(depth);
+                self.contexts.push(BTreeMap::new());
+            }
+            let mut instance = template.clone();
+            let suffix = match (labels, key) {
+                (Some((j, attr)), _) => {
+                    let labels = self.dynamic_labels(attr, node, labeled, src, j)?;
+                    instance.labels = (labels.iter().cloned())
+                        .map(hcl::BlockLabel::String)
+                        .collect();
+                    labels
+                }
+                (None, Some(key)) if !indexed => {
+                    instance.labels.push(hcl::BlockLabel::String(key.clone()));
+                    vec![key]
+                }
+                (None, None) if single => vec![],
+                _ => vec![i.to_string()],
+            };
+            let frame = if indexed {
+                format!("{}[{i}]", Self::frame(&instance))
+            } else {
+                Self::frame(&instance)
+            };
+            let scope = [&path[..], &suffix].concat();
+            self.create_scope(&scope);
+            self.scope = scope;
+            let origin = Origin {
+                addr: addr.clone(),
+                frames: [&node.frames[..node.frames.len() - 1], &[frame]].concat(),
+                bounds: [&node.bounds[..], &[node.scope.len()]].concat(),
+                instance: dynamic.is_none(),
+            };
+            errors.extend(self.parse_body(&mut instance.body, &origin, src, warnings)?);
+            if dynamic.is_none() {
+                instance
+                    .body
+                    .0
+                    .retain(|structure| !Self::is_meta_arg(structure));
+            }
+            out.push(instance.into());
+        }
+        Ok((out, errors))
+    }
+
+    /// Evaluate the meta-arguments of a block into its [`Iterations`], see [`Self::expand()`].
+    ///
+    /// Returns `None` if the block is disabled with `_enabled = false`.
+    fn iterations(
+        &mut self,
+        block: &hcl::Block,
+        node: &Node,
+        labeled: &BTreeSet<Vec<String>>,
+        src: &Source<'_>,
+    ) -> Res<Option<Iterations>> {
+        let mut meta = BTreeMap::new();
+        for (i, attr) in Self::meta_args(block) {
+            let location = Self::locate_in(node, i, attr, src);
+            match self.evaluate(&mut attr.expr.clone(), node, labeled) {
+                Ok(val) => meta.insert(attr.key.as_str(), (val, location)),
+                Err(err) => return Err(err.at(location)),
+            };
+        }
+        match meta.remove("_enabled") {
+            None | Some((Value::Bool(true), _)) => {}
+            Some((Value::Bool(false), _)) => return Ok(None),
+            Some((val, location)) => return Err(unexpected(val, "a bool", location)),
+        }
+        let items = match (meta.remove("for_each"), meta.remove("count")) {
+            (Some(_), Some((_, location))) => {
+                let err =
+                    ErrorKind::Message("`count` and `for_each` cannot be used together".into());
+                return Err(crate::Error::from(hcl::eval::Error::from(err)).at(location));
+            }
+            (Some((val, location)), None) => {
+                let items = match val {
+                    Value::Object(obj) => obj.into_iter().collect_vec(),
+                    // lists are keyed by index in `dynamic` blocks, like in Terraform
+                    Value::Array(arr) if Self::iterator(block).is_some() => (arr.into_iter())
+                        .enumerate()
+                        .map(|(i, v)| (i.to_string(), v))
+                        .collect(),
+                    Value::Array(arr) if arr.iter().all(Value::is_string) => (arr.into_iter())
+                        .filter_map(|v| Some((v.as_str()?.to_owned(), v)))
+                        .collect(),
+                    val => return Err(unexpected(val, "an object or a list of strings", location)),
+                };
+                (items.into_iter())
+                    .map(|(key, value)| {
+                        let iterator = [("key", Value::from(key.clone())), ("value", value)];
+                        (Some(key), Value::from_iter(iterator))
+                    })
+                    .collect()
+            }
+            (None, Some((val, location))) => {
+                let Some(len) = val.as_u64().and_then(|n| usize::try_from(n).ok()) else {
+                    return Err(unexpected(val, "a non-negative integer", location));
+                };
+                (0..len)
+                    .map(|i| (None, Value::from_iter([("index", i)])))
+                    .collect()
+            }
+            (None, None) if Self::iterator(block).is_some() => {
+                let err = ErrorKind::Message("`dynamic` blocks require `for_each`".into());
+                return Err(crate::Error::from(hcl::eval::Error::from(err)).at(node.location(src)));
+            }
+            (None, None) => vec![(None, Value::Null)],
+        };
+        Ok(Some(items))
+    }
+
+    /// Evaluate the `labels` of an instance of a `dynamic` block, which is the attribute at index
+    /// `i` in the block of `node`.
+    fn dynamic_labels(
+        &mut self,
+        attr: &hcl::Attribute,
+        node: &Node,
+        labeled: &BTreeSet<Vec<String>>,
+        src: &Source<'_>,
+        i: usize,
+    ) -> Res<Vec<String>> {
+        let location = Self::locate_in(node, i, attr, src);
+        let val = (self.evaluate(&mut attr.expr.clone(), node, labeled))
+            .map_err(|e| e.at(location.clone()))?;
+        match val {
+            Value::Array(labels) if labels.iter().all(Value::is_string) => Ok((labels.iter())
+                .filter_map(Value::as_str)
+                .map(str::to_owned)
+                .collect()),
+            val => Err(unexpected(val, "a list of strings", location)),
+        }
+    }
+
+    /// Locate `attr`, the structure at index `i` in the block of `node`.
+    fn locate_in(node: &Node, i: usize, attr: &hcl::Attribute, src: &Source<'_>) -> Location {
+        let span = src.spans.get(&[&node.addr[..], &[i]].concat());
+        let path = (node.frames.iter().map(String::as_str))
+            .chain([attr.key.as_str()])
+            .join(" > ");
+        src.locate(&node.addr, span.cloned().unwrap_or_default(), path)
+    }
+
+    /// Attach "did you mean" suggestions to an evaluation error of `node`, for undefined
+    /// variables, missing keys and undefined functions.
+    ///
+    /// Variables are looked up in [`Self::varlist`] under [`Self::scope`], which is the scope of
+    /// `node` during evaluation.
+    fn suggest(&self, err: hcl::eval::Error, node: &Node) -> crate::Error {
+        let visible = || self.varlist.scope_to_hcl_value(&self.scope);
+        let (name, candidates) = match err.kind() {
+            ErrorKind::UndefinedVar(var) => {
+                let keys = visible().as_object().map(|o| o.keys().cloned().collect());
+                (var.to_string(), keys.unwrap_or_default())
+            }
+            ErrorKind::UndefinedFunc(func) => {
+                (func.to_string(), self.fn_names.iter().cloned().collect())
+            }
+            ErrorKind::NoSuchKey(key) => {
+                // find the object in which the key is missing by following the references
+                let root = visible();
+                let keys = node.refs.iter().find_map(|r| {
+                    let mut cur = &root;
+                    for seg in r {
+                        let obj = cur.as_object()?;
+                        match obj.get(seg) {
+                            Some(v) => cur = v,
+                            None if seg == key => return Some(obj.keys().cloned().collect()),
+                            None => return None,
+                        }
+                    }
+                    None
+                });
+                (key.clone(), keys.unwrap_or_default())
+            }
+            _ => return err.into(),
+        };
+        let suggestions = similar_names(&name, candidates);
+        if suggestions.is_empty() {
+            return err.into();
+        }
+        crate::Error::Suggestions {
+            source: Box::new(err.into()),
+            suggestions,
+        }
+    }
+
     /// Parse the string from hcl to an [`hcl::Body`] object.
     ///
     /// ### Differences between this and [`ensan::parse()`]
     /// - if you use the same engine to parse multiple times, the items from the previous strings
-    /// would still be accessible in the following parses:
+    ///   would still be accessible in the following parses:
     /// ```
     /// let mut en = ensan::Engine::new();
     /// let _ = en.parse_str(r#"foo = "bar""#).unwrap();
@@ -286,27 +2272,206 @@ impl Engine<'_> {
     /// let _ = en.parse_str(r#"again = foo"#).unwrap_err(); // nope
     /// ```
     /// - if you want to parse multiple different strings with the same set of hcl functions, it
-    /// is better to use the same `Engine` and just [`clean_up()`] every time after pasing.
+    ///   is better to use the same `Engine` and just [`clean_up()`] every time after pasing.
     ///
     /// # Errors
     /// The following scenarios would terminate the function immediately:
     /// - failure to evalutate an hcl expression
     /// - syntax error
+    /// - attributes referencing each other in a cycle
+    ///
+    /// The errors carry the [`Location`] where they happened. See [`Self::collect_errors`] for
+    /// reporting all evaluation errors at once.
     pub fn parse_str(&mut self, content: impl AsRef<str>) -> Res<hcl::Body> {
-        let mut body = hcl::parse(content.as_ref())?;
-        let mut ctx = self.ctx_init.clone();
-        self.varlist.populate_hcl_ctx(&mut ctx, &self.scope);
-        for structure in &mut body {
-            self.parse_struct(structure, &mut ctx)?;
+        self.parse_with_diagnostics(content).map(|(body, _)| body)
+    }
+
+    /// Parse the string from hcl to an [`hcl::Body`] object, along with the warnings found in it,
+    /// see [`Self::parse_str()`].
+    ///
+    /// The warnings are [`Diagnostic`]s with [`Severity::Warning`] for:
+    /// - attributes defined more than once in the same scope through repeated blocks,
+    ///   which would otherwise silently overwrite each other
+    /// - attributes shadowing variables set with [`Self::set_var()`]
+    /// - calls to functions marked with [`Self::deprecate_fn()`]
+    ///
+    /// # Errors
+    /// Same as [`Self::parse_str()`].
+    ///
+    /// # Examples
+    /// ```
+    /// let mut en = ensan::Engine::new();
+    /// let hcl = "blk \"a\" {\n  x = 1\n}\nblk \"a\" {\n  x = 2\n}";
+    /// let (_, warnings) = en.parse_with_diagnostics(hcl).unwrap();
+    /// assert_eq!(warnings[0].message, "`blk.a.x` is defined more than once, the last value wins");
+    /// assert_eq!(warnings[0].location.as_ref().unwrap().line, 5);
+    /// ```
+    pub fn parse_with_diagnostics(
+        &mut self,
+        content: impl AsRef<str>,
+    ) -> Res<(hcl::Body, Vec<Diagnostic>)> {
+        self.parse_sources(&[(None, content.as_ref())])
+    }
+
+    /// Parse the string from hcl to an [`hcl::Body`] object, see [`Self::parse_str()`].
+    ///
+    /// `file` is the name of the file the string is from, which is included in the
+    /// [`Location`] of errors.
+    ///
+    /// # Errors
+    /// Same as [`Self::parse_str()`].
+    ///
+    /// # Examples
+    /// ```
+    /// let mut en = ensan::Engine::new();
+    /// let err = en.parse_named("main.hcl", "blk \"one\" {\n  hai = nope\n}").unwrap_err();
+    /// let loc = err.location().unwrap();
+    /// assert_eq!((loc.file.as_deref(), loc.line, loc.column), (Some("main.hcl"), 2, 9));
+    /// assert_eq!(loc.path, r#"blk "one" > hai"#);
+    /// ```
+    pub fn parse_named(
+        &mut self,
+        file: impl AsRef<str>,
+        content: impl AsRef<str>,
+    ) -> Res<hcl::Body> {
+        self.parse_named_with_diagnostics(file, content)
+            .map(|(body, _)| body)
+    }
+
+    /// Parse the string from hcl to an [`hcl::Body`] object, along with the warnings found in it,
+    /// see [`Self::parse_named()`] and [`Self::parse_with_diagnostics()`].
+    ///
+    /// # Errors
+    /// Same as [`Self::parse_str()`].
+    pub fn parse_named_with_diagnostics(
+        &mut self,
+        file: impl AsRef<str>,
+        content: impl AsRef<str>,
+    ) -> Res<(hcl::Body, Vec<Diagnostic>)> {
+        self.parse_sources(&[(Some(file.as_ref()), content.as_ref())])
+    }
+
+    /// Parse the files at `paths` as a single body, see [`Self::parse_str()`].
+    ///
+    /// The attributes in one file can reference the ones in the others, and the errors carry the
+    /// path of the file they happened in as [`Location::file`].
+    ///
+    /// # Errors
+    /// Same as [`Self::parse_str()`], or failure to read the files.
+    ///
+    /// # Examples
+    /// ```
+    /// let dir = std::env::temp_dir().join("ensan-doc-parse-files");
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.hcl"), "x = y + 1").unwrap();
+    /// std::fs::write(dir.join("b.hcl"), "y = 1\nz = nope").unwrap();
+    /// let mut en = ensan::Engine::new();
+    /// // `y` is defined in the other file
+    /// assert!(en.parse_files([dir.join("a.hcl")]).is_err());
+    /// en.clean_up();
+    /// let err = en.parse_files([dir.join("a.hcl"), dir.join("b.hcl")]).unwrap_err();
+    /// let loc = err.location().unwrap();
+    /// assert!(loc.file.as_deref().unwrap().ends_with("b.hcl"));
+    /// assert_eq!((loc.line, loc.column), (2, 5));
+    /// ```
+    pub fn parse_files(
+        &mut self,
+        paths: impl IntoIterator<Item = impl AsRef<std::path::Path>>,
+    ) -> Res<hcl::Body> {
+        let files = (paths.into_iter())
+            .map(|path| {
+                let path = path.as_ref();
+                let text = std::fs::read_to_string(path).map_err(|source| crate::Error::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+                Ok((path.display().to_string(), text))
+            })
+            .collect::<Res<Vec<_>>>()?;
+        let files = (files.iter())
+            .map(|(path, text)| (Some(path.as_str()), text.as_str()))
+            .collect_vec();
+        self.parse_sources(&files).map(|(body, _)| body)
+    }
+
+    /// Parse the file at `path`, see [`Self::parse_files()`].
+    ///
+    /// # Errors
+    /// Same as [`Self::parse_files()`].
+    pub fn parse_file(&mut self, path: impl AsRef<std::path::Path>) -> Res<hcl::Body> {
+        self.parse_files([path])
+    }
+
+    /// Parse all `*.hcl` files directly inside `dir` in the order of their names, see
+    /// [`Self::parse_files()`].
+    ///
+    /// # Errors
+    /// Same as [`Self::parse_files()`], or failure to read the directory.
+    pub fn parse_dir(&mut self, dir: impl AsRef<std::path::Path>) -> Res<hcl::Body> {
+        let dir = dir.as_ref();
+        let io_err = |source| crate::Error::Io {
+            path: dir.to_path_buf(),
+            source,
+        };
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(dir).map_err(io_err)? {
+            let path = entry.map_err(io_err)?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "hcl") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        self.parse_files(paths)
+    }
+
+    /// Parse the strings as `(file, text)` and evaluate them as a single body.
+    fn parse_sources(
+        &mut self,
+        files: &[(Option<&str>, &str)],
+    ) -> Res<(hcl::Body, Vec<Diagnostic>)> {
+        let include_dir = (self.include_dir.as_ref())
+            .map(|dir| {
+                std::fs::canonicalize(dir).map_err(|source| crate::Error::Io {
+                    path: dir.clone(),
+                    source,
+                })
+            })
+            .transpose()?;
+        let mut src = Source {
+            include_dir,
+            ..Source::default()
+        };
+        let mut body = hcl::Body::default();
+        for &(file, text) in files {
+            let parsed = Self::parse_edit(file, text)?;
+            src.files
+                .push((file.map(Cow::Borrowed), Cow::Borrowed(text)));
+            let mut stack = (file.map(std::path::PathBuf::from).into_iter())
+                .map(|path| std::fs::canonicalize(&path).unwrap_or(path))
+                .collect();
+            let idx = src.files.len() - 1;
+            Self::load(&mut src, parsed, idx, &mut vec![], &mut body, &mut stack)?;
+        }
+        // `varlist` and `ctx_init` may have been changed since the last parse
+        self.forget_contexts();
+        let mut warnings = vec![];
+        let errors = self.parse_body(&mut body, &Origin::default(), &src, &mut warnings)?;
+        if self.strip_locals {
+            (body.0)
+                .retain(|s| !matches!(s, hcl::Structure::Block(block) if Self::is_locals(block)));
+        }
+        if errors.is_empty() {
+            Ok((body, warnings))
+        } else {
+            Err(crate::Error::Multiple { errors, body })
         }
-        Ok(body)
     }
 
     /// Parse the string from hcl to an [`hcl::Body`] object.
     ///
     /// ### Differences between this and [`ensan::parse()`]
     /// - if you use the same engine to parse multiple times, the items from the previous strings
-    /// would still be accessible in the following parses:
+    ///   would still be accessible in the following parses:
     /// ```
     /// let mut en = ensan::Engine::new();
     /// let _ = en.parse(r#"foo = "bar""#).unwrap();
@@ -315,13 +2480,42 @@ impl Engine<'_> {
     /// let _ = en.parse(r#"again = foo"#).unwrap_err(); // nope
     /// ```
     /// - if you want to parse multiple different strings with the same set of hcl functions, it
-    /// is better to use the same `Engine` and just [`clean_up()`] every time after pasing.
+    ///   is better to use the same `Engine` and just [`clean_up()`] every time after pasing.
     ///
     /// # Errors
     /// The following scenarios would terminate the function immediately:
     /// - failure to evalutate an hcl expression
     /// - syntax error
+    /// - attributes referencing each other in a cycle
     pub fn parse(&mut self, content: impl AsRef<str>) -> Res<hcl::Body> {
         self.parse_str(content)
     }
 }
+
+/// Find the names among `candidates` that are close to `name`, the closest first.
+fn similar_names(name: &str, candidates: Vec<String>) -> Vec<String> {
+    let max = (name.chars().count() / 3).max(1);
+    (candidates.into_iter())
+        .map(|c| (edit_distance(name, &c), c))
+        .filter(|(d, _)| *d <= max)
+        .sorted()
+        .take(3)
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect_vec();
+    let mut row = (0..=b.len()).collect_vec();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + usize::from(ca != cb)).min(row[j] + 1).min(cur + 1);
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
@@ -1,4 +1,7 @@
 //! Error types for ensan.
+use itertools::Itertools;
+use std::ops::Range;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("HCL error: {0}")]
@@ -7,4 +10,130 @@ pub enum Error {
     HclEvals(#[from] hcl::eval::Errors),
     #[error("Hcl Eval error: {0}")]
     HclEval(#[from] hcl::eval::Error),
+    /// Attributes referencing each other in a cycle, e.g. `["a", "b", "a"]` for `a = b` and
+    /// `b = a`.
+    #[error("Reference cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    /// Files including each other in a cycle through `include` blocks, e.g. `["a.hcl", "b.hcl",
+    /// "a.hcl"]`.
+    #[error("Include cycle: {}", .0.join(" -> "))]
+    IncludeCycle(Vec<String>),
+    /// An `include` or `import` block that cannot be loaded, e.g. because its path is outside of
+    /// [`crate::Engine::include_dir`].
+    #[error("cannot include `{path}`: {message}")]
+    Include { path: String, message: String },
+    /// An error with the location in the hcl strings where it happened.
+    #[error("{location}: {source}")]
+    At {
+        location: Location,
+        source: Box<Self>,
+    },
+    /// An attribute defined again in the same scope, see [`crate::Engine::strict`].
+    #[error("`{path}` conflicts with the definition at {previous}")]
+    Redefined { path: String, previous: Location },
+    /// An error with names close to the undefined one, e.g. `three` for `blk.one.two.thre.x`.
+    #[error("{source}, did you mean {}?", .suggestions.iter().map(|s| format!("`{s}`")).join(" or "))]
+    Suggestions {
+        source: Box<Self>,
+        suggestions: Vec<String>,
+    },
+    /// A `variable` block without a value, or with a value that does not conform to its `type`,
+    /// e.g. `expected number, got "many"`.
+    #[error("variable `{name}`: {message}")]
+    Variable { name: String, message: String },
+    /// A `validation` block whose `condition` is false, with its `error_message`.
+    #[error("{message}")]
+    Validation { message: String },
+    /// Failure to read a file or a directory, see [`crate::Engine::parse_files()`].
+    #[error("failed to read `{}`: {source}", .path.display())]
+    Io {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    /// All the errors from evaluating the hcl strings, along with the partially evaluated body,
+    /// see [`crate::Engine::collect_errors`].
+    #[error("{}", .errors.iter().join("\n"))]
+    Multiple { errors: Vec<Self>, body: hcl::Body },
+}
+
+impl Error {
+    /// Attach a location to the error.
+    #[must_use]
+    pub fn at(self, location: Location) -> Self {
+        Self::At {
+            location,
+            source: Box::new(self),
+        }
+    }
+    /// The location where the error happened, if known.
+    #[must_use]
+    pub const fn location(&self) -> Option<&Location> {
+        match self {
+            Self::At { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+    /// The error without its location and suggestions.
+    #[must_use]
+    pub fn inner(&self) -> &Self {
+        match self {
+            Self::At { source, .. } | Self::Suggestions { source, .. } => source.inner(),
+            _ => self,
+        }
+    }
+    /// The names suggested in place of an undefined one, see [`Self::Suggestions`].
+    #[must_use]
+    pub fn suggestions(&self) -> &[String] {
+        match self {
+            Self::At { source, .. } => source.suggestions(),
+            Self::Suggestions { suggestions, .. } => suggestions,
+            _ => &[],
+        }
+    }
+}
+
+/// A location in the hcl strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// name of the file, if the hcl strings come from a file
+    pub file: Option<String>,
+    /// zero-based byte offsets of the expression
+    pub span: Range<usize>,
+    /// one-based line number
+    pub line: usize,
+    /// one-based column number, in characters
+    pub column: usize,
+    /// path of the attribute, e.g. `blk "one" "two" > again > hai`
+    ///
+    /// This is empty for syntax errors.
+    pub path: String,
+}
+
+impl Location {
+    /// Locate `span` inside `text`.
+    #[must_use]
+    pub fn new(file: Option<&str>, text: &str, span: Range<usize>, path: String) -> Self {
+        let before = text.get(..span.start).unwrap_or(text);
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            file: file.map(str::to_owned),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            span,
+            path,
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+        }
+        write!(f, "{}:{}", self.line, self.column)?;
+        if !self.path.is_empty() {
+            write!(f, " ({})", self.path)?;
+        }
+        Ok(())
+    }
 }
@@ -11,20 +11,257 @@
 //! <https://developer.hashicorp.com/terraform/language/functions> for the full list of functions both implemented and not implemented.
 
 // TODO: Figure out why Value::String values include the quotes, and fix it or report it as a bug upstream!
-use hcl::{eval::FuncArgs, Value};
+use hcl::{eval::ParamType, Value};
 
 type FnRes = Result<Value, String>;
 
-macro_rules! must_let {
-    ($left:pat = $right:expr) => {
-        let $left = $right else { unreachable!() };
-    };
+/// A group of named hcl functions, which can be registered with
+/// [`Engine::register_fns()`](crate::Engine::register_fns).
+///
+/// This is what [`ensan_fn_mod`](crate::ensan_fn_mod) generates from a module.
+pub type FnGroup = Vec<(&'static str, hcl::eval::FuncDef)>;
+
+/// Check and fill in the optional arguments of a function, used by
+/// [`ensan_fn_mod`](crate::ensan_fn_mod) for parameters declared as `opt Type = default`.
+///
+/// `values` are all the arguments passed to the function, the first `required` of which have
+/// already been checked by `hcl-rs`. Missing optional arguments, and the ones given as `null`,
+/// are replaced by their default value; if there is no default, the remaining arguments are left
+/// out.
+#[doc(hidden)]
+pub fn fill_optional_args(
+    values: &mut Vec<Value>,
+    required: usize,
+    optional: impl IntoIterator<Item = (ParamType, Option<Value>)>,
+) -> Result<(), String> {
+    let optional = optional.into_iter().collect::<Vec<_>>();
+    let max = required + optional.len();
+    if values.len() > max {
+        return Err(format!(
+            "expected at most {max} positional arguments, got {}",
+            values.len()
+        ));
+    }
+    for (pos, (param, default)) in optional.into_iter().enumerate() {
+        let pos = required + pos;
+        match (values.get_mut(pos), default) {
+            (Some(arg), Some(default)) if arg.is_null() => *arg = default,
+            (Some(arg), _) if !is_satisfied_by(&param, arg) => {
+                return Err(format!(
+                    "expected argument at position {pos} to be of type {param}, got `{arg}`"
+                ));
+            }
+            (Some(_), _) => {}
+            (None, Some(default)) => values.push(default),
+            (None, None) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Call `func` with `values` as its positional arguments, used by
+/// [`ensan_fn_mod`](crate::ensan_fn_mod) for passing the arguments filled in by
+/// [`fill_optional_args()`] as [`FuncArgs`](hcl::eval::FuncArgs).
+///
+/// `FuncArgs` cannot be created outside of `hcl-rs`, so this evaluates a call to `func` with the
+/// values as literal arguments.
+#[doc(hidden)]
+pub fn call_with_values(func: hcl::eval::Func, values: Vec<Value>) -> Result<Value, String> {
+    use hcl::eval::Evaluate;
+    let mut ctx = hcl::eval::Context::new();
+    let params = vec![ParamType::Any; values.len()];
+    ctx.declare_func("f", hcl::eval::FuncDef::new(func, params));
+    let call = (values.into_iter())
+        .fold(hcl::expr::FuncCall::builder("f"), |call, value| {
+            call.arg(hcl::Expression::from(value))
+        })
+        .build();
+    hcl::Expression::from(call)
+        .evaluate(&ctx)
+        .map_err(|e| match e.kind() {
+            hcl::eval::ErrorKind::FuncCall(_, msg) => msg.clone(),
+            kind => kind.to_string(),
+        })
+}
+
+/// Same as `ParamType::is_satisfied_by()`, which is private in `hcl-rs`.
+fn is_satisfied_by(param: &ParamType, value: &Value) -> bool {
+    match param {
+        ParamType::Any => true,
+        ParamType::Bool => value.is_boolean(),
+        ParamType::Number => value.is_number(),
+        ParamType::String => value.is_string(),
+        ParamType::Array(elem) => {
+            (value.as_array()).is_some_and(|arr| arr.iter().all(|v| is_satisfied_by(elem, v)))
+        }
+        ParamType::Object(elem) => {
+            (value.as_object()).is_some_and(|obj| obj.values().all(|v| is_satisfied_by(elem, v)))
+        }
+        ParamType::Nullable(elem) => value.is_null() || is_satisfied_by(elem, value),
+        ParamType::OneOf(elems) => elems.iter().any(|elem| is_satisfied_by(elem, value)),
+    }
+}
+
+/// A type that a function argument can be converted into.
+///
+/// This is used by [`ensan_fn_mod`](crate::ensan_fn_mod) for functions with typed signatures like
+/// `fn split(sep: &str, s: &str) -> FnRes`, to generate both the [`ParamType`] of each parameter
+/// and the conversion of the arguments.
+pub trait FromArg<'a>: Sized {
+    /// The parameter type checked by `hcl-rs` before calling the function.
+    fn param_type() -> ParamType;
+    /// Convert the argument.
+    fn from_arg(value: &'a Value) -> Result<Self, String>;
+}
+
+fn unexpected<T>(param: &ParamType, value: &Value) -> Result<T, String> {
+    Err(format!("expected type {param}, got `{value}`"))
+}
+
+impl<'a> FromArg<'a> for &'a Value {
+    fn param_type() -> ParamType {
+        ParamType::Any
+    }
+    fn from_arg(value: &'a Value) -> Result<Self, String> {
+        Ok(value)
+    }
+}
+
+impl FromArg<'_> for Value {
+    fn param_type() -> ParamType {
+        ParamType::Any
+    }
+    fn from_arg(value: &Value) -> Result<Self, String> {
+        Ok(value.clone())
+    }
+}
+
+impl<'a> FromArg<'a> for &'a str {
+    fn param_type() -> ParamType {
+        ParamType::String
+    }
+    fn from_arg(value: &'a Value) -> Result<Self, String> {
+        (value.as_str()).map_or_else(|| unexpected(&Self::param_type(), value), Ok)
+    }
+}
+
+impl FromArg<'_> for String {
+    fn param_type() -> ParamType {
+        ParamType::String
+    }
+    fn from_arg(value: &Value) -> Result<Self, String> {
+        <&str>::from_arg(value).map(ToOwned::to_owned)
+    }
+}
+
+impl FromArg<'_> for bool {
+    fn param_type() -> ParamType {
+        ParamType::Bool
+    }
+    fn from_arg(value: &Value) -> Result<Self, String> {
+        (value.as_bool()).map_or_else(|| unexpected(&Self::param_type(), value), Ok)
+    }
+}
+
+impl<'a> FromArg<'a> for &'a hcl::Number {
+    fn param_type() -> ParamType {
+        ParamType::Number
+    }
+    fn from_arg(value: &'a Value) -> Result<Self, String> {
+        (value.as_number()).map_or_else(|| unexpected(&Self::param_type(), value), Ok)
+    }
+}
+
+impl FromArg<'_> for f64 {
+    fn param_type() -> ParamType {
+        ParamType::Number
+    }
+    fn from_arg(value: &Value) -> Result<Self, String> {
+        (value.as_f64()).map_or_else(|| unexpected(&Self::param_type(), value), Ok)
+    }
+}
+
+macro_rules! impl_from_arg_int {
+    ($($int:ty)*) => {$(
+        impl FromArg<'_> for $int {
+            fn param_type() -> ParamType {
+                ParamType::Number
+            }
+            fn from_arg(value: &Value) -> Result<Self, String> {
+                let n = <&hcl::Number>::from_arg(value)?;
+                (n.as_i64().and_then(|n| Self::try_from(n).ok()))
+                    .or_else(|| n.as_u64().and_then(|n| Self::try_from(n).ok()))
+                    .ok_or_else(|| format!("`{n}` is not a valid {}", stringify!($int)))
+            }
+        }
+    )*};
+}
+
+impl_from_arg_int!(i32 i64 u32 u64 usize);
+
+impl<'a, T: FromArg<'a>> FromArg<'a> for Option<T> {
+    fn param_type() -> ParamType {
+        ParamType::Nullable(Box::new(T::param_type()))
+    }
+    fn from_arg(value: &'a Value) -> Result<Self, String> {
+        if value.is_null() {
+            return Ok(None);
+        }
+        T::from_arg(value).map(Some)
+    }
+}
+
+impl<'a, T: FromArg<'a>> FromArg<'a> for Vec<T> {
+    fn param_type() -> ParamType {
+        ParamType::Array(Box::new(T::param_type()))
+    }
+    fn from_arg(value: &'a Value) -> Result<Self, String> {
+        let Some(arr) = value.as_array() else {
+            return unexpected(&Self::param_type(), value);
+        };
+        arr.iter().map(T::from_arg).collect()
+    }
+}
+
+impl<'a> FromArg<'a> for &'a [Value] {
+    fn param_type() -> ParamType {
+        ParamType::Array(Box::new(ParamType::Any))
+    }
+    fn from_arg(value: &'a Value) -> Result<Self, String> {
+        (value.as_array()).map_or_else(
+            || unexpected(&Self::param_type(), value),
+            |arr| Ok(arr.as_slice()),
+        )
+    }
+}
+
+impl<'a, T: FromArg<'a>> FromArg<'a> for hcl::Map<String, T> {
+    fn param_type() -> ParamType {
+        ParamType::Object(Box::new(T::param_type()))
+    }
+    fn from_arg(value: &'a Value) -> Result<Self, String> {
+        let Some(obj) = value.as_object() else {
+            return unexpected(&Self::param_type(), value);
+        };
+        (obj.iter())
+            .map(|(k, v)| Ok((k.clone(), T::from_arg(v)?)))
+            .collect()
+    }
+}
+
+impl<'a> FromArg<'a> for &'a hcl::Map<String, Value> {
+    fn param_type() -> ParamType {
+        ParamType::Object(Box::new(ParamType::Any))
+    }
+    fn from_arg(value: &'a Value) -> Result<Self, String> {
+        (value.as_object()).map_or_else(|| unexpected(&Self::param_type(), value), Ok)
+    }
 }
 
 #[cfg(feature = "fn-encoding")]
-#[ensan_proc_macro::ensan_internal_fn_mod(encoding)]
+#[ensan_proc_macro::ensan_fn_mod(encoding)]
 pub mod encoding {
-    use super::{FnRes, FuncArgs, Value};
+    use super::{FnRes, Value};
     use base64::prelude::*;
 
     /// Serializes YAML from a string to HCL
@@ -39,10 +276,8 @@ pub mod encoding {
     /// let expected = ensan::parse(r#"hi = { key = "value" }"#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn yamldecode(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(arg)] = &args[..]);
-
+    #[ensan_fn]
+    pub fn yamldecode(arg: &str) -> FnRes {
         serde_yml::from_str(arg).map_err(|e| format!("Failed to deserialize YAML: {e}"))
     }
 
@@ -66,10 +301,8 @@ pub mod encoding {
     /// assert_eq!(eval, expected);
     /// ```
     // todo: fix Object type
-    #[ensan_fn(Any)]
-    pub fn yamlencode(args: FuncArgs) -> FnRes {
-        must_let!([arg] = &args[..]);
-
+    #[ensan_fn]
+    pub fn yamlencode(arg: &Value) -> FnRes {
         let ymlstring = serde_yml::to_string(arg)
             .map_err(|e| format!("Failed to serialize YAML: {e}"))?
             .trim()
@@ -90,14 +323,13 @@ pub mod encoding {
     /// let expected = ensan::parse(r#"hi = { key = "value" }"#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn jsondecode(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(arg)] = &args[..]);
-
+    #[ensan_fn]
+    pub fn jsondecode(arg: &str) -> FnRes {
         serde_json::from_str(arg).map_err(|e| format!("Failed to deserialize JSON: {e}"))
     }
 
     #[test]
+    #[allow(clippy::expect_used)]
     fn test_jsondecode() {
         crate::parse(r"hi = jsondecode()").expect_err("jsondecode() runs without args");
         crate::parse(r"hi = jsondecode(1)").expect_err("jsondecode() runs with wrong-type args");
@@ -115,10 +347,8 @@ pub mod encoding {
     /// let expected = ensan::parse(r#"hi = "{\"key\":\"value\"}""#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(Any)]
-    pub fn jsonencode(args: FuncArgs) -> FnRes {
-        must_let!([arg] = &args[..]);
-
+    #[ensan_fn]
+    pub fn jsonencode(arg: &Value) -> FnRes {
         let jsonstring = serde_json::to_string(arg)
             .map_err(|e| format!("Failed to serialize JSON: {e}"))?
             .trim()
@@ -138,10 +368,8 @@ pub mod encoding {
     /// let expected = ensan::parse(r#"hi = "aGVsbG8=""#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn base64encode(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(arg)] = &args[..]);
-
+    #[ensan_fn]
+    pub fn base64encode(arg: &str) -> FnRes {
         let encoded = BASE64_STANDARD.encode(arg.as_bytes());
         Ok(Value::String(encoded))
     }
@@ -158,10 +386,8 @@ pub mod encoding {
     /// let expected = ensan::parse(r#"hi = "hello""#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn base64decode(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(arg)] = &args[..]);
-
+    #[ensan_fn]
+    pub fn base64decode(arg: &str) -> FnRes {
         let decoded = BASE64_STANDARD
             .decode(arg.as_bytes())
             .map_err(|e| format!("Failed to decode base64: {e}"))?;
@@ -172,10 +398,10 @@ pub mod encoding {
 }
 
 #[cfg(feature = "fn-strings")]
-#[ensan_proc_macro::ensan_internal_fn_mod(string_manipulation)]
+#[ensan_proc_macro::ensan_fn_mod(string_manipulation)]
 /// This module contains string manipulation functions.
 pub mod string_manipulation {
-    use super::{FnRes, FuncArgs, Value};
+    use super::{FnRes, Value};
     use itertools::Itertools;
 
     /// Make all characters in a string lowercase
@@ -194,10 +420,8 @@ pub mod string_manipulation {
     /// "#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn lower(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(arg)] = &args[..]);
-
+    #[ensan_fn]
+    pub fn lower(arg: &str) -> FnRes {
         Ok(Value::String(arg.to_lowercase()))
     }
 
@@ -213,10 +437,8 @@ pub mod string_manipulation {
     /// let expected = ensan::parse(r#"hi = "HELLO""#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn upper(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(arg)] = &args[..]);
-
+    #[ensan_fn]
+    pub fn upper(arg: &str) -> FnRes {
         Ok(Value::String(arg.to_uppercase()))
     }
 
@@ -232,12 +454,10 @@ pub mod string_manipulation {
     /// let expected = ensan::parse(r#"hi = ["a", "b", "c"]"#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String, String)]
-    pub fn split(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(sep), Value::String(args)] = &args[..]);
-
+    #[ensan_fn]
+    pub fn split(sep: &str, s: &str) -> FnRes {
         Ok(Value::Array(
-            args.split(sep)
+            s.split(sep)
                 .map(ToString::to_string)
                 .map(Value::String)
                 .collect(),
@@ -256,13 +476,9 @@ pub mod string_manipulation {
     /// let expected = ensan::parse(r#"hi = "a,b,c""#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String, Array(String))]
-    pub fn join(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(sep), Value::Array(args)] = &args[..]);
-
-        Ok(Value::String(
-            args.iter().filter_map(Value::as_str).join(sep),
-        ))
+    #[ensan_fn]
+    pub fn join(sep: &str, list: Vec<&str>) -> FnRes {
+        Ok(Value::String(list.iter().join(sep)))
     }
 
     /// Get the length of a string
@@ -277,9 +493,8 @@ pub mod string_manipulation {
     /// let expected = ensan::parse(r#"hi = 5"#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn strlen(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(s)] = &args[..]);
+    #[ensan_fn]
+    pub fn strlen(s: &str) -> FnRes {
         Ok(s.len().into())
     }
 
@@ -295,9 +510,8 @@ pub mod string_manipulation {
     /// let expected = ensan::parse(r#"hi = "hello""#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn trimspace(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(s)] = &args[..]);
+    #[ensan_fn]
+    pub fn trimspace(s: &str) -> FnRes {
         Ok(s.trim().into())
     }
 
@@ -313,18 +527,157 @@ pub mod string_manipulation {
     /// let expected = ensan::parse(r#"hi = "olleh""#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn strrev(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(s)] = &args[..]);
+    #[ensan_fn]
+    pub fn strrev(s: &str) -> FnRes {
         let reversed: String = s.chars().rev().collect();
         Ok(Value::String(reversed))
     }
 }
 
+#[cfg(feature = "fn-collections")]
+#[ensan_proc_macro::ensan_fn_mod(collections)]
+/// This module contains functions for working with lists and objects.
+pub mod collections {
+    use super::{FnRes, Value};
+
+    /// Get the first argument that is not null or an empty string
+    ///
+    /// Accepts: Any, ...Any
+    ///
+    /// Returns: Any
+    ///
+    /// Example:
+    /// ```
+    /// let eval = ensan::parse(r#"hi = coalesce(null, "", "a", "b")"#).unwrap();
+    /// let expected = ensan::parse(r#"hi = "a""#).unwrap();
+    /// assert_eq!(eval, expected);
+    /// ```
+    #[ensan_fn]
+    pub fn coalesce(first: &Value, #[variadic] rest: Vec<&Value>) -> FnRes {
+        std::iter::once(first)
+            .chain(rest)
+            .find(|v| !v.is_null() && v.as_str() != Some(""))
+            .cloned()
+            .ok_or_else(|| "no non-null, non-empty-string arguments".to_owned())
+    }
+
+    /// Combine multiple lists into a single list
+    ///
+    /// Accepts: ...[Any]
+    ///
+    /// Returns: [Any]
+    ///
+    /// Example:
+    /// ```
+    /// let eval = ensan::parse(r#"hi = concat(["a"], [], ["b", "c"])"#).unwrap();
+    /// let expected = ensan::parse(r#"hi = ["a", "b", "c"]"#).unwrap();
+    /// assert_eq!(eval, expected);
+    /// ```
+    #[ensan_fn]
+    pub fn concat(#[variadic] lists: Vec<&[Value]>) -> FnRes {
+        Ok(Value::Array(lists.concat()))
+    }
+
+    /// Get the number of elements in a list or object, or the number of characters in a string
+    ///
+    /// Accepts: [Any] | {Any} | String
+    ///
+    /// Returns: Number
+    ///
+    /// Example:
+    /// ```
+    /// let eval = ensan::parse(r#"hi = [length([1, 2]), length({ a = 1 }), length("nya")]"#).unwrap();
+    /// let expected = ensan::parse(r#"hi = [2, 1, 3]"#).unwrap();
+    /// assert_eq!(eval, expected);
+    /// ```
+    #[ensan_fn]
+    pub fn length(value: &Value) -> FnRes {
+        match value {
+            Value::Array(arr) => Ok(arr.len().into()),
+            Value::Object(obj) => Ok(obj.len().into()),
+            Value::String(s) => Ok(s.chars().count().into()),
+            _ => Err(format!("expected a list, object or string, got `{value}`")),
+        }
+    }
+
+    /// Merge multiple objects into a single object, later keys take precedence
+    ///
+    /// Accepts: ...{Any}
+    ///
+    /// Returns: {Any}
+    ///
+    /// Example:
+    /// ```
+    /// let eval = ensan::parse(r#"hi = merge({ a = 1, b = 2 }, { b = 3 })"#).unwrap();
+    /// let expected = ensan::parse(r#"hi = { a = 1, b = 3 }"#).unwrap();
+    /// assert_eq!(eval, expected);
+    /// ```
+    #[ensan_fn]
+    pub fn merge(#[variadic] objs: Vec<&hcl::Map<String, Value>>) -> FnRes {
+        Ok(Value::Object(
+            objs.into_iter()
+                .flatten()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        ))
+    }
+
+    /// Get the value of a key in an object, or the default value if the key does not exist
+    ///
+    /// Accepts: {Any}, String, optional Any
+    ///
+    /// Returns: Any
+    ///
+    /// Example:
+    /// ```
+    /// let eval = ensan::parse(r#"
+    /// a = lookup({ a = 1 }, "a")
+    /// b = lookup({ a = 1 }, "b", 2)
+    /// "#).unwrap();
+    /// let expected = ensan::parse("a = 1\nb = 2").unwrap();
+    /// assert_eq!(eval, expected);
+    /// ```
+    #[ensan_fn]
+    pub fn lookup(
+        map: &hcl::Map<String, Value>,
+        key: &str,
+        #[opt] default: Option<&Value>,
+    ) -> FnRes {
+        map.get(key)
+            .or(default)
+            .cloned()
+            .ok_or_else(|| format!("no key `{key}` in object and no default value"))
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_variadic() {
+        crate::parse(r"hi = coalesce()").expect_err("coalesce() runs without args");
+        crate::parse(r"hi = coalesce(null)").expect_err("coalesce() finds a value in null");
+        crate::parse(r"hi = concat([1], 2)").expect_err("concat() runs with wrong-type args");
+        let eval = crate::parse(r"hi = concat()").expect("concat() runs without args");
+        assert_eq!(eval, crate::parse(r"hi = []").expect("parse"));
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_typed_args() {
+        crate::parse(r"hi = lookup({ a = 1 }, 1)").expect_err("lookup() runs with a number key");
+        let eval = crate::parse(r#"hi = lookup({ a = 1 }, "b", null)"#).expect("lookup() runs");
+        assert_eq!(eval, crate::parse("hi = null").expect("parse"));
+        crate::parse(r#"hi = lookup({ a = 1 }, "b")"#)
+            .expect_err("lookup() runs without a key or a default");
+        crate::parse(r#"hi = lookup({ a = 1 }, "a", 2, 3)"#)
+            .expect_err("lookup() runs with too many args");
+        let eval = crate::parse(r#"hi = lookup({ a = 1 }, "a", null)"#).expect("lookup() runs");
+        assert_eq!(eval, crate::parse(r"hi = 1").expect("parse"));
+    }
+}
+
 #[cfg(feature = "fn-misc")]
-#[ensan_proc_macro::ensan_internal_fn_mod(ensan_builtin_fns)]
+#[ensan_proc_macro::ensan_fn_mod(ensan_builtin_fns)]
 pub mod ensan_internal_fns {
-    use super::{FnRes, FuncArgs, Value};
+    use super::FnRes;
 
     /// Get value from environment variable
     ///
@@ -340,9 +693,8 @@ pub mod ensan_internal_fns {
     /// let expected = ensan::parse(r#"hi = "bar""#).unwrap();
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn env(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(key)] = &args[..]);
+    #[ensan_fn]
+    pub fn env(key: &str) -> FnRes {
         Ok(std::env::var(key)
             .map_err(|e| format!("Failed to get environment variable: {e}"))?
             .into())
@@ -350,11 +702,9 @@ pub mod ensan_internal_fns {
 }
 
 #[cfg(feature = "fn-hashing")]
-#[ensan_proc_macro::ensan_internal_fn_mod(hashing)]
+#[ensan_proc_macro::ensan_fn_mod(hashing)]
 pub mod hashing {
-    use super::{FnRes, FuncArgs, Value};
-
-    // todo: add bcrypt function once we figure out how to do optional arguments
+    use super::FnRes;
 
     /// Hash a string using the MD5 algorithm
     ///
@@ -385,10 +735,9 @@ pub mod hashing {
     ///
     /// ```
     ///
-    #[ensan_fn(String)]
-    pub fn md5(args: FuncArgs) -> FnRes {
+    #[ensan_fn]
+    pub fn md5(s: &str) -> FnRes {
         use md5::{Digest, Md5};
-        must_let!([Value::String(s)] = &args[..]);
         let mut hasher = Md5::new();
         hasher.update(s);
         Ok(format!("{:x}", hasher.finalize()).into())
@@ -421,10 +770,9 @@ pub mod hashing {
     ///
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn sha1(args: FuncArgs) -> FnRes {
+    #[ensan_fn]
+    pub fn sha1(s: &str) -> FnRes {
         use sha1::{Digest, Sha1};
-        must_let!([Value::String(s)] = &args[..]);
         let mut hasher = Sha1::new();
         hasher.update(s.as_bytes());
         Ok(format!("{:x}", hasher.finalize()).into())
@@ -456,10 +804,9 @@ pub mod hashing {
     ///
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn sha256(args: FuncArgs) -> FnRes {
+    #[ensan_fn]
+    pub fn sha256(s: &str) -> FnRes {
         use sha2::{Digest, Sha256};
-        must_let!([Value::String(s)] = &args[..]);
         let mut hasher = Sha256::new();
         hasher.update(s.as_bytes());
         Ok(format!("{:x}", hasher.finalize()).into())
@@ -491,55 +838,55 @@ pub mod hashing {
     ///
     /// assert_eq!(eval, expected);
     /// ```
-    #[ensan_fn(String)]
-    pub fn sha512(args: FuncArgs) -> FnRes {
+    #[ensan_fn]
+    pub fn sha512(s: &str) -> FnRes {
         use sha2::{Digest, Sha512};
-        must_let!([Value::String(s)] = &args[..]);
         let mut hasher = Sha512::new();
         hasher.update(s.as_bytes());
         Ok(format!("{:x}", hasher.finalize()).into())
     }
-    // todo: make Nullable work when only one argument is passed?
     /// Hash a string using bcrypt
     ///
-    /// Accepts: String, Nullable(Number)
+    /// Accepts: String, optional Number (cost, defaults to 10, also if `null`)
     ///
     /// Returns: String
     ///
     /// Example:
     ///
-    /// ```ignore // Test is ignored here because bcrypt is not deterministic, so we can't really compare the output
-    /// use bcrypt::hash;
-    ///
-    /// let ex_str = "hello";
-    ///
-    /// let hash_result = hash(ex_str, 10).unwrap();
-    ///
-    /// let hcl_str = format!(r#"hi = bcrypt("{}", 10)"#, ex_str);
-    /// let eval = ensan::parse(&hcl_str).unwrap();
-    ///
-    /// let expected = ensan::parse(&format!(r#"hi = "{}""#, hash_result)).unwrap();
+    /// ```
+    /// let eval = ensan::parse(r#"hi = bcrypt("hello")"#).unwrap();
+    /// let hash = eval.attributes().next().unwrap().expr.to_string();
+    /// assert!(bcrypt::verify("hello", hash.trim_matches('"')).unwrap());
     ///
-    /// assert_eq!(eval, expected);
+    /// let eval = ensan::parse(r#"hi = bcrypt("hello", 4)"#).unwrap();
+    /// let hash = eval.attributes().next().unwrap().expr.to_string();
+    /// assert!(hash.starts_with(r#""$2b$04$"#));
     /// ```
-    #[ensan_fn(String, Nullable(Number))]
-    pub fn bcrypt(args: FuncArgs) -> FnRes {
+    #[ensan_fn]
+    pub fn bcrypt(s: &str, #[opt = 10] cost: u32) -> FnRes {
         use bcrypt::hash;
-        // Ok, time to do this the old-fashioned way
 
-        must_let!([Value::String(s), cost] = &args[..]);
-        let cost = (cost.as_u64().unwrap_or(10).try_into())
-            .map_err(|e| format!("Cannot turn u64 → u32: {e}"))?;
         Ok(hash(s, cost)
             .map_err(|e| format!("Failed to hash string with bcrypt: {e}"))?
             .into())
     }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_bcrypt() {
+        crate::parse(r#"hi = bcrypt("a", "b")"#).expect_err("bcrypt() runs with wrong-type args");
+        crate::parse(r#"hi = bcrypt("a", 4, 5)"#).expect_err("bcrypt() runs with too many args");
+        // `null` gives the default cost
+        let body = crate::parse(r#"hi = bcrypt("a", null)"#).expect("bcrypt() runs with null");
+        let hash = body.attributes().next().expect("hi").expr.to_string();
+        assert!(hash.starts_with(r#""$2b$10$"#), "{hash}");
+    }
 }
 
 #[cfg(feature = "fn-uuid")]
-#[ensan_proc_macro::ensan_internal_fn_mod(uuid)]
+#[ensan_proc_macro::ensan_fn_mod(uuid)]
 pub mod uuid {
-    use super::{uuid, FnRes, FuncArgs, Value};
+    use super::{uuid, FnRes};
     use ::uuid::Uuid;
 
     /// Generate a random UUID
@@ -548,21 +895,94 @@ pub mod uuid {
     ///
     /// Returns: String
     ///
-    #[ensan_fn()]
-    pub fn uuidv4(_args: FuncArgs) -> FnRes {
+    #[ensan_fn]
+    pub fn uuidv4() -> FnRes {
         Ok(uuid::Uuid::new_v4().to_string().into())
     }
 
-    /// Generate a UUIDv5 from a namespace and a name
+    /// Generate a `UUIDv5` from a namespace and a name
     ///
     /// Accepts: String, String
     ///
     /// Returns: String
     ///
-    #[ensan_fn(String, String)]
-    pub fn uuidv5(args: FuncArgs) -> FnRes {
-        must_let!([Value::String(ns), Value::String(name)] = &args[..]);
+    #[ensan_fn]
+    pub fn uuidv5(ns: &str, name: &str) -> FnRes {
         let ns = Uuid::parse_str(ns).map_err(|e| format!("Failed to parse UUID: {e}"))?;
         Ok(Uuid::new_v5(&ns, name.as_bytes()).to_string().into())
     }
 }
+
+/// Functions with the parameter types listed in `#[ensan_fn(...)]`, which take `FuncArgs`, for
+/// testing the untyped forms of [`ensan_fn_mod`](crate::ensan_fn_mod).
+#[cfg(test)]
+#[ensan_proc_macro::ensan_fn_mod(untyped_fns)]
+pub mod untyped {
+    use super::FnRes;
+    use hcl::eval::FuncArgs;
+
+    /// Pad a string on the right to a width, with spaces unless a filler is given.
+    #[ensan_fn(String, opt Number = 4, opt String)]
+    pub fn pad(args: FuncArgs) -> FnRes {
+        let s = args[0].as_str().unwrap_or_default();
+        let width = (args[1].as_u64())
+            .and_then(|w| usize::try_from(w).ok())
+            .ok_or_else(|| format!("invalid width `{}`", args[1]))?;
+        let fill = args.get(2).and_then(|v| v.as_str()).unwrap_or(" ");
+        let len = s.chars().count();
+        Ok(format!("{s}{}", fill.repeat(width.saturating_sub(len))).into())
+    }
+
+    /// Join the arguments after the first one, with the first one as the separator.
+    #[ensan_fn(String, ..Any)]
+    pub fn join_with(args: FuncArgs) -> FnRes {
+        let sep = args[0].as_str().unwrap_or_default();
+        let parts = args.variadic_args().map(|v| match v {
+            hcl::Value::String(s) => s.clone(),
+            v => v.to_string(),
+        });
+        Ok(parts.collect::<Vec<_>>().join(sep).into())
+    }
+
+    /// The message of the error from evaluating `hcl`.
+    fn error(en: &mut crate::Engine, hcl: &str) -> String {
+        en.parse(hcl)
+            .map_or_else(|e| e.inner().to_string(), |body| format!("{body:?}"))
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_variadic() {
+        let mut en = crate::Engine::new();
+        en.register_fns(super::untyped_fns());
+        let body = (en.parse("a = join_with(\"-\")\nb = join_with(\"-\", \"x\", 1, true)"))
+            .expect("join_with() runs");
+        let expected = crate::parse("a = \"\"\nb = \"x-1-true\"").expect("parse");
+        assert_eq!(body, expected);
+        assert!(
+            error(&mut en, "a = join_with()").contains("expected 1 positional arguments, got 0")
+        );
+        assert!(error(&mut en, "a = join_with(1, \"x\")")
+            .contains("expected argument at position 0 to be of type `string`, got `1`"));
+    }
+
+    #[test]
+    #[allow(clippy::expect_used)]
+    fn test_optional() {
+        let mut en = crate::Engine::new();
+        en.register_fns(super::untyped_fns());
+        let hcl =
+            "a = pad(\"ab\")\nb = pad(\"ab\", 3)\nc = pad(\"ab\", 5, \"-\")\nd = pad(\"ab\", null)";
+        let body = en.parse(hcl).expect("pad() runs");
+        let expected = "a = \"ab  \"\nb = \"ab \"\nc = \"ab---\"\nd = \"ab  \"";
+        let expected = crate::parse(expected).expect("parse");
+        assert_eq!(body, expected);
+        assert!(error(&mut en, "a = pad()").contains("expected 1 positional arguments, got 0"));
+        assert!(error(&mut en, "a = pad(\"ab\", 5, \"-\", 1)")
+            .contains("expected at most 3 positional arguments, got 4"));
+        assert!(error(&mut en, "a = pad(\"ab\", \"5\")")
+            .contains("expected argument at position 1 to be of type `number`, got `\"5\"`"));
+        assert!(error(&mut en, "a = pad(\"ab\", 5, null)")
+            .contains("expected argument at position 2 to be of type `string`, got `null`"));
+    }
+}
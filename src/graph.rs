@@ -0,0 +1,379 @@
+//! # Reference graph
+//!
+//! Helpers used by [`crate::Engine`] to figure out which attributes an expression refers to, and
+//! in which order attributes should be evaluated so that references to things defined later in
+//! the document can still be resolved.
+use hcl::{
+    expr::{ForExpr, TemplateExpr, Traversal, TraversalOperator, Variable},
+    template::{Directive, Element},
+    Expression, Identifier, ObjectKey, Template,
+};
+
+/// A reference to a variable, e.g. `blk.one.two.foo` is `["blk", "one", "two", "foo"]`.
+///
+/// Only the statically-known part of a traversal is included, so `arr[0]` is `["arr", "0"]`
+/// but `arr[idx]` and `arr[*].foo` are both just `["arr"]`.
+pub type Reference = Vec<String>;
+
+/// Everything used by an expression.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub refs: Vec<Reference>,
+    /// names of the called functions
+    pub funcs: Vec<String>,
+}
+
+/// Collect all references and function calls inside an expression.
+///
+/// Variables that are bound by `for` expressions and template `for` directives are not
+/// considered as references.
+#[must_use]
+pub fn usage(expr: &Expression) -> Usage {
+    let mut usage = Usage::default();
+    visit_expr(expr, &mut vec![], &mut usage);
+    usage
+}
+
+/// Collect all references inside an expression, see [`usage()`].
+#[cfg(test)]
+fn references(expr: &Expression) -> Vec<Reference> {
+    usage(expr).refs
+}
+
+fn visit_expr(expr: &Expression, bound: &mut Vec<String>, out: &mut Usage) {
+    match expr {
+        Expression::Variable(var) if !bound.iter().any(|b| b == var.as_str()) => {
+            out.refs.push(vec![var.to_string()]);
+        }
+        Expression::Traversal(traversal) => {
+            let mut operators = traversal.operators.iter();
+            if let Expression::Variable(var) = &traversal.expr {
+                if !bound.iter().any(|b| b == var.as_str()) {
+                    let mut path = vec![var.to_string()];
+                    for op in operators.by_ref() {
+                        match op {
+                            TraversalOperator::GetAttr(key) => path.push(key.to_string()),
+                            TraversalOperator::LegacyIndex(i) => path.push(i.to_string()),
+                            TraversalOperator::Index(Expression::Number(n)) => {
+                                path.push(n.to_string());
+                            }
+                            TraversalOperator::Index(Expression::String(s)) => {
+                                path.push(s.clone());
+                            }
+                            TraversalOperator::Index(idx) => {
+                                visit_expr(idx, bound, out);
+                                break;
+                            }
+                            TraversalOperator::AttrSplat | TraversalOperator::FullSplat => break,
+                        }
+                    }
+                    out.refs.push(path);
+                }
+            } else {
+                visit_expr(&traversal.expr, bound, out);
+            }
+            for op in operators {
+                if let TraversalOperator::Index(idx) = op {
+                    visit_expr(idx, bound, out);
+                }
+            }
+        }
+        Expression::Array(arr) => arr.iter().for_each(|e| visit_expr(e, bound, out)),
+        Expression::Object(obj) => obj.iter().for_each(|(k, v)| {
+            if let ObjectKey::Expression(k) = k {
+                visit_expr(k, bound, out);
+            }
+            visit_expr(v, bound, out);
+        }),
+        Expression::TemplateExpr(template) => {
+            // parse errors are reported during evaluation instead
+            if let Ok(template) = Template::from_expr(template) {
+                visit_template(&template, bound, out);
+            }
+        }
+        Expression::FuncCall(call) => {
+            out.funcs.push(call.name.to_string());
+            call.args.iter().for_each(|e| visit_expr(e, bound, out));
+        }
+        Expression::Parenthesis(expr) => visit_expr(expr, bound, out),
+        Expression::Conditional(cond) => {
+            visit_expr(&cond.cond_expr, bound, out);
+            visit_expr(&cond.true_expr, bound, out);
+            visit_expr(&cond.false_expr, bound, out);
+        }
+        Expression::Operation(op) => match &**op {
+            hcl::Operation::Unary(op) => visit_expr(&op.expr, bound, out),
+            hcl::Operation::Binary(op) => {
+                visit_expr(&op.lhs_expr, bound, out);
+                visit_expr(&op.rhs_expr, bound, out);
+            }
+        },
+        Expression::ForExpr(for_expr) => {
+            visit_expr(&for_expr.collection_expr, bound, out);
+            let old_len = bound.len();
+            bound.extend(for_expr.key_var.iter().map(ToString::to_string));
+            bound.push(for_expr.value_var.to_string());
+            if let Some(key_expr) = &for_expr.key_expr {
+                visit_expr(key_expr, bound, out);
+            }
+            visit_expr(&for_expr.value_expr, bound, out);
+            if let Some(cond_expr) = &for_expr.cond_expr {
+                visit_expr(cond_expr, bound, out);
+            }
+            bound.truncate(old_len);
+        }
+        _ => {}
+    }
+}
+
+fn visit_template(template: &Template, bound: &mut Vec<String>, out: &mut Usage) {
+    for element in template.elements() {
+        match element {
+            Element::Literal(_) => {}
+            Element::Interpolation(interp) => visit_expr(&interp.expr, bound, out),
+            Element::Directive(Directive::If(dir)) => {
+                visit_expr(&dir.cond_expr, bound, out);
+                visit_template(&dir.true_template, bound, out);
+                if let Some(false_template) = &dir.false_template {
+                    visit_template(false_template, bound, out);
+                }
+            }
+            Element::Directive(Directive::For(dir)) => {
+                visit_expr(&dir.collection_expr, bound, out);
+                let old_len = bound.len();
+                bound.extend(dir.key_var.iter().map(ToString::to_string));
+                bound.push(dir.value_var.to_string());
+                visit_template(&dir.template, bound, out);
+                bound.truncate(old_len);
+            }
+        }
+    }
+}
+
+/// Call `f` on every expression inside `expr`, innermost first, ending with `expr` itself.
+///
+/// The expressions inside templates are parsed out and walked too; the template is written back
+/// only if `f` changed any of them.
+pub fn walk_mut(expr: &mut Expression, f: &mut impl FnMut(&mut Expression)) {
+    match expr {
+        Expression::Traversal(traversal) => {
+            walk_mut(&mut traversal.expr, f);
+            for op in &mut traversal.operators {
+                if let TraversalOperator::Index(idx) = op {
+                    walk_mut(idx, f);
+                }
+            }
+        }
+        Expression::Array(arr) => arr.iter_mut().for_each(|e| walk_mut(e, f)),
+        Expression::Object(obj) => obj.iter_mut().for_each(|(_, v)| walk_mut(v, f)),
+        Expression::FuncCall(call) => call.args.iter_mut().for_each(|e| walk_mut(e, f)),
+        Expression::Parenthesis(expr) => walk_mut(expr, f),
+        Expression::Conditional(cond) => {
+            walk_mut(&mut cond.cond_expr, f);
+            walk_mut(&mut cond.true_expr, f);
+            walk_mut(&mut cond.false_expr, f);
+        }
+        Expression::Operation(op) => match &mut **op {
+            hcl::Operation::Unary(op) => walk_mut(&mut op.expr, f),
+            hcl::Operation::Binary(op) => {
+                walk_mut(&mut op.lhs_expr, f);
+                walk_mut(&mut op.rhs_expr, f);
+            }
+        },
+        Expression::ForExpr(for_expr) => {
+            walk_mut(&mut for_expr.collection_expr, f);
+            if let Some(key_expr) = &mut for_expr.key_expr {
+                walk_mut(key_expr, f);
+            }
+            walk_mut(&mut for_expr.value_expr, f);
+            if let Some(cond_expr) = &mut for_expr.cond_expr {
+                walk_mut(cond_expr, f);
+            }
+        }
+        Expression::TemplateExpr(template_expr) => {
+            let Ok(mut template) = Template::from_expr(template_expr) else {
+                return f(expr);
+            };
+            let old = template.clone();
+            walk_template_mut(&mut template, f);
+            if template != old {
+                match &mut **template_expr {
+                    TemplateExpr::QuotedString(raw) => *raw = template.to_string(),
+                    TemplateExpr::Heredoc(heredoc) => heredoc.template = template.to_string(),
+                }
+            }
+        }
+        _ => {}
+    }
+    f(expr);
+}
+
+/// Call [`walk_mut()`] on every expression inside `template`.
+fn walk_template_mut(template: &mut Template, f: &mut impl FnMut(&mut Expression)) {
+    for element in template.elements_mut() {
+        match element {
+            Element::Literal(_) => {}
+            Element::Interpolation(interp) => walk_mut(&mut interp.expr, f),
+            Element::Directive(Directive::If(dir)) => {
+                walk_mut(&mut dir.cond_expr, f);
+                walk_template_mut(&mut dir.true_template, f);
+                if let Some(false_template) = &mut dir.false_template {
+                    walk_template_mut(false_template, f);
+                }
+            }
+            Element::Directive(Directive::For(dir)) => {
+                walk_mut(&mut dir.collection_expr, f);
+                walk_template_mut(&mut dir.template, f);
+            }
+        }
+    }
+}
+
+/// Name of the variable bound by the `for` expressions from [`expand_splats()`].
+const SPLAT_VAR: &str = "__ensan_splat";
+
+/// Rewrite splats over labeled blocks, e.g. `service.*.port`, into `for` expressions over their
+/// values, e.g. `[for s in service : s.port]`.
+///
+/// `hcl-rs` treats a splat on an object as a splat on a list of that one object, which is not
+/// useful for labeled blocks since they are objects keyed by the labels. `is_labeled` tells
+/// whether the path before a splat (e.g. `["service"]`) refers to labeled blocks.
+pub fn expand_splats(expr: &mut Expression, is_labeled: &impl Fn(&[String]) -> bool) {
+    walk_mut(expr, &mut |e| {
+        let Expression::Traversal(traversal) = e else {
+            return;
+        };
+        let Expression::Variable(var) = &traversal.expr else {
+            return;
+        };
+        let mut path = vec![var.to_string()];
+        let mut pos = 0;
+        let full_splat = loop {
+            match traversal.operators.get(pos) {
+                Some(TraversalOperator::GetAttr(key)) => path.push(key.to_string()),
+                Some(TraversalOperator::AttrSplat) => break false,
+                Some(TraversalOperator::FullSplat) => break true,
+                _ => return,
+            }
+            pos += 1;
+        };
+        if !is_labeled(&path) {
+            return;
+        }
+        let (before, rest) = traversal.operators.split_at(pos);
+        let rest = &rest[1..];
+        // attribute-only splats (`.*`) only apply the attribute accesses right after them
+        let inner_len = if full_splat {
+            rest.len()
+        } else {
+            (rest.iter())
+                .take_while(|op| matches!(op, TraversalOperator::GetAttr(_)))
+                .count()
+        };
+        let var = Expression::Variable(var.clone());
+        let collection = if before.is_empty() {
+            var
+        } else {
+            Traversal::new(var, before.to_vec()).into()
+        };
+        let splat_var = Expression::Variable(Variable::unchecked(SPLAT_VAR));
+        let value = if inner_len == 0 {
+            splat_var
+        } else {
+            Traversal::new(splat_var, rest[..inner_len].to_vec()).into()
+        };
+        let for_expr = ForExpr::new(Identifier::unchecked(SPLAT_VAR), collection, value).into();
+        *e = if inner_len == rest.len() {
+            for_expr
+        } else {
+            Traversal::new(for_expr, rest[inner_len..].to_vec()).into()
+        };
+    });
+}
+
+/// Sort the nodes of a graph such that each node comes after all of its dependencies.
+///
+/// `deps[i]` lists the indices of the nodes that node `i` depends on. Independent nodes keep
+/// their original order.
+///
+/// # Errors
+/// If the graph contains a cycle, the nodes forming it are returned in the order they reference
+/// each other, with the first node repeated at the end (e.g. `[a, b, a]`).
+pub fn toposort(deps: &[Vec<usize>]) -> Result<Vec<usize>, Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Mark {
+        New,
+        Visiting,
+        Done,
+    }
+    fn visit(
+        i: usize,
+        deps: &[Vec<usize>],
+        marks: &mut [Mark],
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), Vec<usize>> {
+        match marks[i] {
+            Mark::Done => return Ok(()),
+            Mark::Visiting => {
+                let start = stack.iter().rposition(|&n| n == i).unwrap_or_default();
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(i);
+                return Err(cycle);
+            }
+            Mark::New => {}
+        }
+        marks[i] = Mark::Visiting;
+        stack.push(i);
+        for &dep in &deps[i] {
+            visit(dep, deps, marks, stack, order)?;
+        }
+        stack.pop();
+        marks[i] = Mark::Done;
+        order.push(i);
+        Ok(())
+    }
+    let mut marks = vec![Mark::New; deps.len()];
+    let mut order = Vec::with_capacity(deps.len());
+    for i in 0..deps.len() {
+        visit(i, deps, &mut marks, &mut vec![], &mut order)?;
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references() {
+        let body = hcl::parse(
+            r#"
+            a = foo.bar[0].baz[idx]
+            b = [for k, v in obj : "${k}${v.x}${other}"]
+            c = upper(blk.one.two) ? x : y.*.z
+            "#,
+        )
+        .unwrap();
+        let refs = body
+            .attributes()
+            .map(|a| references(&a.expr))
+            .collect::<Vec<_>>();
+        assert_eq!(refs[0], [vec!["idx"], vec!["foo", "bar", "0", "baz"]]);
+        assert_eq!(refs[1], [vec!["obj"], vec!["other"]]);
+        assert_eq!(refs[2], [vec!["blk", "one", "two"], vec!["x"], vec!["y"]]);
+        let expr = &body.attributes().nth(2).unwrap().expr;
+        assert_eq!(usage(expr).funcs, ["upper"]);
+    }
+
+    #[test]
+    fn test_toposort() {
+        assert_eq!(toposort(&[vec![1], vec![], vec![0]]), Ok(vec![1, 0, 2]));
+        assert_eq!(
+            toposort(&[vec![1], vec![2], vec![0]]),
+            Err(vec![0, 1, 2, 0])
+        );
+        assert_eq!(toposort(&[vec![], vec![1]]), Err(vec![1, 1]));
+    }
+}
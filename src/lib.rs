@@ -18,13 +18,22 @@
 #![allow(clippy::implicit_return)]
 #![allow(clippy::blanket_clippy_restriction_lints)]
 #![allow(clippy::pattern_type_mismatch)]
+extern crate self as ensan;
+
+pub mod diagnostics;
 pub mod engine;
 pub mod errors;
 pub mod functions;
+mod graph;
 pub mod tests;
+mod types;
 
+pub use diagnostics::{Diagnostic, Severity};
 pub use engine::Engine;
+pub use ensan_proc_macro::ensan_fn_mod;
 pub use errors::Error;
+/// Re-export of `hcl-rs`, which is used in the public API of ensan.
+pub use hcl;
 
 /// Quickly evaluate an HCL file
 ///
@@ -1,4 +1,5 @@
 #![cfg(test)]
+#![allow(clippy::unwrap_used)]
 
 #[test]
 fn test_nested_block_with_3_labels() {
@@ -29,3 +30,1017 @@ fn test_nested_block_with_3_labels() {
         "#;
     assert_eq!(en.parse(hcl).unwrap(), en.clean_up().parse(expect).unwrap());
 }
+
+#[test]
+fn test_forward_references() {
+    let mut en = crate::Engine::new();
+    let hcl = r#"
+        a = b
+        wow = blk.one.foo
+        blk "one" {
+            foo = "${bar}!"
+            bar = obj.key
+            obj = { key = lower(x) }
+            x = "HAI"
+        }
+        b = 1
+        "#;
+    let expect = r#"
+        a = 1
+        wow = "hai!"
+        blk "one" {
+            foo = "hai!"
+            bar = "hai"
+            obj = { key = "hai" }
+            x = "HAI"
+        }
+        b = 1
+        "#;
+    assert_eq!(en.parse(hcl).unwrap(), en.clean_up().parse(expect).unwrap());
+}
+
+#[test]
+fn test_reference_cycle() {
+    let mut en = crate::Engine::new();
+    let hcl = r#"
+        blk "x" {
+            a = c
+            c = "${b}"
+            b = a
+        }
+        "#;
+    let err = en.parse(hcl).unwrap_err();
+    assert!(
+        matches!(err.inner(), crate::Error::Cycle(c) if c == &["blk.x.a", "blk.x.c", "blk.x.b", "blk.x.a"]),
+        "{err}"
+    );
+    assert_eq!(
+        err.inner().to_string(),
+        "Reference cycle: blk.x.a -> blk.x.c -> blk.x.b -> blk.x.a"
+    );
+    assert_eq!(err.location().unwrap().path, r#"blk "x" > a"#);
+}
+
+#[test]
+fn test_error_location() {
+    let mut en = crate::Engine::new();
+    let hcl = r#"
+blk "one" "two" {
+    again {
+        hai = upper(nope)
+    }
+}
+"#;
+    let err = en.parse_named("main.hcl", hcl).unwrap_err();
+    let loc = err.location().unwrap();
+    assert_eq!(loc.file.as_deref(), Some("main.hcl"));
+    assert_eq!((loc.line, loc.column), (4, 15));
+    assert_eq!(&hcl[loc.span.clone()], "upper(nope)");
+    assert_eq!(loc.path, r#"blk "one" "two" > again > hai"#);
+    assert!(err
+        .to_string()
+        .starts_with(r#"main.hcl:4:15 (blk "one" "two" > again > hai): "#));
+
+    let err = en.parse("a = 1\nb = ").unwrap_err();
+    assert!(matches!(err.inner(), crate::Error::Hcl(_)));
+    assert_eq!(err.location().unwrap().line, 2);
+}
+
+#[test]
+fn test_set_var() {
+    #[derive(serde::Serialize)]
+    struct Input {
+        name: String,
+        replicas: u32,
+    }
+    let mut en = crate::Engine::new();
+    en.set_var(
+        "var.app",
+        Input {
+            name: "nya".into(),
+            replicas: 3,
+        },
+    )
+    .unwrap();
+    en.set_var("var.region", "us-east-1").unwrap();
+    en.set_var("var.region", "eu-west-1").unwrap();
+    let hcl = r#"
+        name = "${var.app.name}-${var.region}"
+        total = var.app.replicas * 2
+        "#;
+    let expect = r#"
+        name = "nya-eu-west-1"
+        total = 6
+        "#;
+    assert_eq!(en.parse(hcl).unwrap(), hcl::from_str(expect).unwrap());
+    // inputs are kept across clean_up()
+    let body = en.clean_up().parse("again = var.region").unwrap();
+    assert_eq!(body, hcl::from_str(r#"again = "eu-west-1""#).unwrap());
+}
+
+#[test]
+fn test_register_fn() {
+    use hcl::eval::{FuncDef, ParamType};
+    let mut en = crate::Engine::new().with_fn(
+        "twice",
+        FuncDef::new(
+            |args| Ok(args[0].as_str().unwrap_or_default().repeat(2).into()),
+            [ParamType::String],
+        ),
+    );
+    en.register_closure("answer", [], |_| Ok(42.into()));
+    let hcl = r#"
+        a = twice("nya")
+        b = answer()
+        "#;
+    let expect = r#"
+        a = "nyanya"
+        b = 42
+        "#;
+    assert_eq!(en.parse(hcl).unwrap(), hcl::from_str(expect).unwrap());
+    // functions are kept across clean_up()
+    let body = en.clean_up().parse("c = answer()").unwrap();
+    assert_eq!(body, hcl::from_str("c = 42").unwrap());
+}
+
+#[test]
+fn test_collect_errors() {
+    let mut en = crate::Engine::new();
+    en.collect_errors = true;
+    let hcl = r#"
+        a = b
+        b = a
+        c = a
+        blk "x" {
+            d = nope
+            e = "${d}!"
+            f = upper("ok")
+        }
+        g = blk.x.f
+        "#;
+    let crate::Error::Multiple { errors, body } = en.parse(hcl).unwrap_err() else {
+        panic!("expected multiple errors");
+    };
+    let paths = errors
+        .iter()
+        .map(|e| e.location().unwrap().path.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, ["a", r#"blk "x" > d"#]);
+    assert!(matches!(errors[0].inner(), crate::Error::Cycle(_)));
+    let expected = r#"
+        a = b
+        b = a
+        c = a
+        blk "x" {
+            d = nope
+            e = "${d}!"
+            f = "OK"
+        }
+        g = "OK"
+        "#;
+    assert_eq!(body, hcl::parse(expected).unwrap());
+}
+
+#[test]
+fn test_render_diagnostics() {
+    let mut en = crate::Engine::new();
+    let hcl = "blk \"one\" {\n    again {\n        hai = upper(nope)\n    }\n}\n";
+    let err = en.parse_named("main.hcl", hcl).unwrap_err();
+    let expected = r#"error: undefined variable `nope`
+ --> main.hcl:3:15
+  |
+3 |         hai = upper(nope)
+  |               ^^^^^^^^^^^
+  = in: blk "one" > again > hai"#;
+    assert_eq!(err.render(&[("main.hcl", hcl)], false), expected);
+    let colored = err.render(&[("main.hcl", hcl)], true);
+    assert!(colored.contains("\x1b[1;31m^^^^^^^^^^^\x1b[0m"));
+    // the snippet is left out when the file is not in the sources
+    let expected =
+        "error: undefined variable `nope`\n --> main.hcl:3:15\n  = in: blk \"one\" > again > hai";
+    assert_eq!(err.render(hcl, false), expected);
+
+    let hcl = "a = b\nb = a\n";
+    let diags = crate::Diagnostic::from_error(&en.parse(hcl).unwrap_err());
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].severity, crate::Severity::Error);
+    assert_eq!(diags[0].hints.len(), 1);
+    assert!(diags[0]
+        .render(hcl, false)
+        .ends_with("= hint: break the cycle by removing one of the references"));
+}
+
+#[test]
+fn test_render_multiple_files() {
+    let dir = std::env::temp_dir().join(format!("ensan-test-render-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.hcl"), "a = 1\nx = nope").unwrap();
+    std::fs::write(dir.join("b.hcl"), "\n\nb = upper(1)").unwrap();
+    let mut en = crate::Engine::new();
+    en.collect_errors = true;
+    let err = en.parse_dir(&dir).unwrap_err();
+    let rendered = err.render(&crate::diagnostics::FileSystem, false);
+    assert!(rendered.contains("2 | x = nope\n  |     ^^^^"));
+    assert!(rendered.contains("3 | b = upper(1)\n  |     ^^^^^^^^"));
+
+    let sources = std::collections::BTreeMap::from([(
+        dir.join("b.hcl").display().to_string(),
+        "\n\nb = upper(1)",
+    )]);
+    let rendered = err.render(&sources, false);
+    assert!(!rendered.contains("x = nope"));
+    assert!(rendered.contains("3 | b = upper(1)"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_suggestions() {
+    let mut en = crate::Engine::new();
+    let hcl = r#"
+        blk "one" "two" "three" {
+            x = 1
+        }
+        a = blk.one.two.thre.x
+        "#;
+    let err = en.parse(hcl).unwrap_err();
+    assert_eq!(err.suggestions(), ["three"]);
+    assert!(
+        err.to_string().ends_with(", did you mean `three`?"),
+        "{err}"
+    );
+
+    en.clean_up();
+    let err = en.parse(r#"a = lowr("X")"#).unwrap_err();
+    assert_eq!(err.suggestions(), ["lower"]);
+    let diags = crate::Diagnostic::from_error(&err);
+    assert_eq!(diags[0].hints, ["did you mean `lower`?"]);
+
+    en.clean_up();
+    let err = en.parse("foo = 1\nbar = fo0 + fooo").unwrap_err();
+    assert_eq!(err.suggestions(), ["foo"]);
+    let err = en.parse("bar = completely_different").unwrap_err();
+    assert!(err.suggestions().is_empty());
+}
+
+#[test]
+fn test_warnings() {
+    let mut en = crate::Engine::new();
+    en.set_var("region", "eu").unwrap();
+    en.deprecate_fn("strrev", "use something else");
+    let hcl = r#"
+        region = "us"
+        blk "a" {
+            x = strrev("ab")
+        }
+        blk "a" {
+            x = strrev(strrev("ab"))
+        }
+        y = blk.a.x
+        "#;
+    let (body, warnings) = en.parse_with_diagnostics(hcl).unwrap();
+    let warnings = warnings
+        .iter()
+        .map(|w| {
+            assert_eq!(w.severity, crate::Severity::Warning);
+            (w.location.as_ref().unwrap().line, w.message.as_str())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        [
+            (
+                2,
+                "`region` shadows a variable set with `Engine::set_var()`"
+            ),
+            (4, "function `strrev` is deprecated"),
+            (
+                7,
+                "`blk.a.x` is defined more than once, the last value wins"
+            ),
+            (7, "function `strrev` is deprecated"),
+        ]
+    );
+    let y = body.attributes().find(|a| a.key.as_str() == "y").unwrap();
+    assert_eq!(y.expr, "ab".into());
+}
+
+#[test]
+fn test_strict() {
+    let hcl = r#"
+        blk = { a = 1 }
+        blk "a" {
+            x = 1
+        }
+        other "b" {
+            y = 1
+        }
+        other "b" {
+            z = 2
+        }
+        "#;
+    // merging blocks without conflicts is fine
+    let mut en = crate::Engine::new();
+    en.parse(hcl).unwrap();
+    en.clean_up();
+    en.strict = true;
+    let err = en.parse(hcl).unwrap_err();
+    let crate::Error::Redefined { path, previous } = err.inner() else {
+        panic!("expected a redefined error, got {err}");
+    };
+    assert_eq!(path, "blk.a.x");
+    assert_eq!((previous.line, previous.path.as_str()), (2, "blk"));
+    assert_eq!(err.location().unwrap().line, 4);
+
+    en.clean_up();
+    en.collect_errors = true;
+    let hcl = "a \"x\" {\n  b = 1\n}\na \"x\" {\n  b = 2\n}\na \"x\" {\n  b = 3\n}";
+    let crate::Error::Multiple { errors, .. } = en.parse(hcl).unwrap_err() else {
+        panic!("expected multiple errors");
+    };
+    let lines = errors
+        .iter()
+        .map(|e| e.location().unwrap().line)
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [5, 8]);
+}
+
+#[test]
+fn test_repeated_blocks() {
+    let hcl = r#"
+        rule {
+            name = "a"
+        }
+        rule {
+            name = "b"
+            check {
+                x = 1
+            }
+            check {
+                x = "${upper("ab")}"
+            }
+        }
+        rule {}
+        single {
+            name = "c"
+        }
+        second = rule[1].name
+        n = length(rule)
+        xs = rule[1].check[*].x
+        names = [for r in rule : lookup(r, "name", "-")]
+        s = single[0].name
+        n1 = length(single)
+        names1 = [for b in single : b.name]
+        "#;
+    let expected = r#"
+        rule {
+            name = "a"
+        }
+        rule {
+            name = "b"
+            check {
+                x = 1
+            }
+            check {
+                x = "AB"
+            }
+        }
+        rule {}
+        single {
+            name = "c"
+        }
+        second = "b"
+        n = 3
+        xs = [1, "AB"]
+        names = ["a", "b", "-"]
+        s = "c"
+        n1 = 1
+        names1 = ["c"]
+        "#;
+    let mut en = crate::Engine::new();
+    en.strict = true;
+    en.block_lists = true;
+    assert_eq!(en.parse(hcl).unwrap(), hcl::parse(expected).unwrap());
+
+    // without `block_lists`, the blocks are objects and repeating one redefines its attributes
+    en.clean_up().block_lists = false;
+    let hcl = "rule {\n  name = \"a\"\n}\nrule {\n  name = \"b\"\n}\nn = rule.name";
+    let err = en.parse(hcl).unwrap_err();
+    assert!(
+        matches!(err.inner(), crate::Error::Redefined { .. }),
+        "{err}"
+    );
+    assert_eq!(
+        en.clean_up()
+            .parse("single {\n  name = \"c\"\n}\nn = single.name")
+            .unwrap(),
+        hcl::parse("single {\n  name = \"c\"\n}\nn = \"c\"").unwrap()
+    );
+}
+
+#[test]
+fn test_labeled_splat() {
+    let hcl = r#"
+        ports = service.*.port
+        names = service[*].meta.name
+        first = service.*.port[0]
+        from_for = [for k, s in service : "${k}:${s.port}"]
+        hosts = [for h in host.*.web.addr : upper(h)]
+        service "a" {
+            port = 1
+            meta {
+                name = "x"
+            }
+        }
+        service "b" {
+            port = 2
+            meta {
+                name = "y"
+            }
+        }
+        host "one" "web" {
+            addr = "a.example"
+        }
+        host "two" "web" {
+            addr = "b.example"
+        }
+        list = [{ port = 3 }]
+        plain = list.*.port
+        tpl_first = "${service.*.port[0]}"
+        tpl_len = "n=${length(service.*.port)} \"${(service[*].meta.name)[1]}\""
+        tpl_for = <<-EOT
+        %{ for p in service.*.port }${p};%{ endfor }
+        EOT
+        "#;
+    let body = crate::parse(hcl).unwrap();
+    let get = |key: &str| {
+        body.attributes()
+            .find(|a| a.key.as_str() == key)
+            .unwrap()
+            .expr
+            .clone()
+    };
+    assert_eq!(get("ports"), hcl::expression!([1, 2]));
+    assert_eq!(get("names"), hcl::expression!(["x", "y"]));
+    assert_eq!(get("first"), 1.into());
+    assert_eq!(get("from_for"), hcl::expression!(["a:1", "b:2"]));
+    assert_eq!(get("hosts"), hcl::expression!(["A.EXAMPLE", "B.EXAMPLE"]));
+    assert_eq!(get("plain"), hcl::expression!([3]));
+    assert_eq!(get("tpl_first"), 1.into());
+    assert_eq!(get("tpl_len"), r#"n=2 "y""#.into());
+    assert_eq!(get("tpl_for"), "1;2;\n".into());
+}
+
+#[test]
+fn test_for_each_count() {
+    let hcl = r#"
+        envs = { dev = 1, prod = 3 }
+        server {
+            for_each = envs
+            name = "srv-${each.key}"
+            replicas = each.value * 2
+            tag {
+                env = upper(each.key)
+            }
+        }
+        worker "w" {
+            count = length(envs)
+            id = "w${count.index}"
+        }
+        dev = server.dev.replicas
+        ids = worker.w[*].id
+        names = server.*.name
+        "#;
+    let expected = r#"
+        envs = { "dev" = 1, "prod" = 3 }
+        server "dev" {
+            name = "srv-dev"
+            replicas = 2
+            tag {
+                env = "DEV"
+            }
+        }
+        server "prod" {
+            name = "srv-prod"
+            replicas = 6
+            tag {
+                env = "PROD"
+            }
+        }
+        worker "w" {
+            id = "w0"
+        }
+        worker "w" {
+            id = "w1"
+        }
+        dev = 2
+        ids = ["w0", "w1"]
+        names = ["srv-dev", "srv-prod"]
+        "#;
+    let mut en = crate::Engine::new();
+    assert_eq!(en.parse(hcl).unwrap(), hcl::parse(expected).unwrap());
+    let prod = en.varlist.list_in_scope_ref(&["server", "prod"]);
+    assert!(prod
+        .map(crate::engine::VarScope::to_hcl_var)
+        .any(|(k, _)| k == "name"));
+
+    let err = crate::parse("blk {\n  for_each = 1\n}").unwrap_err();
+    assert_eq!(err.location().unwrap().path, "blk > for_each");
+    let err = crate::parse("blk {\n  count = 1\n  for_each = []\n}").unwrap_err();
+    assert!(err.to_string().contains("cannot be used together"));
+}
+
+#[test]
+fn test_dynamic_blocks() {
+    let hcl = r#"
+        firewall {
+            name = "web"
+            ports = [80, 443]
+            dynamic "ingress" {
+                for_each = ports
+                content {
+                    port = ingress.value
+                    desc = "rule ${ingress.key}"
+                }
+            }
+            dynamic "route" {
+                for_each = { a = "10.0.0.0/8" }
+                iterator = r
+                labels = [upper(r.key)]
+                content {
+                    cidr = r.value
+                }
+            }
+            last = ingress[1].port
+        }
+        "#;
+    let expected = r#"
+        firewall {
+            name = "web"
+            ports = [80, 443]
+            ingress {
+                port = 80
+                desc = "rule 0"
+            }
+            ingress {
+                port = 443
+                desc = "rule 1"
+            }
+            route "A" {
+                cidr = "10.0.0.0/8"
+            }
+            last = 443
+        }
+        "#;
+    let mut en = crate::Engine::new();
+    assert_eq!(en.parse(hcl).unwrap(), hcl::parse(expected).unwrap());
+
+    let err = crate::parse("dynamic \"x\" {\n  content {}\n}").unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("`dynamic` blocks require `for_each`"));
+}
+
+#[test]
+fn test_enabled_blocks() {
+    let hcl = r#"
+        debug = false
+        feature "metrics" {
+            _enabled = !debug
+            port = 9090
+        }
+        feature "tracing" {
+            _enabled = debug
+            port = 4317
+        }
+        worker {
+            _enabled = debug
+            count = 2
+        }
+        ports = [for f in feature : f.port]
+        "#;
+    let expected = r#"
+        debug = false
+        feature "metrics" {
+            port = 9090
+        }
+        ports = [9090]
+        "#;
+    let mut en = crate::Engine::new();
+    assert_eq!(en.parse(hcl).unwrap(), hcl::parse(expected).unwrap());
+    let scopes = en.varlist.list_in_scope_ref(&["feature"]);
+    let names = scopes.map(|v| v.to_hcl_var().0).collect::<Vec<_>>();
+    assert_eq!(names, ["metrics"]);
+
+    let err = crate::parse("blk {\n  _enabled = 1\n}").unwrap_err();
+    assert_eq!(err.location().unwrap().path, "blk > _enabled");
+    let err = en.parse("x = worker").unwrap_err();
+    assert!(matches!(
+        err.inner(),
+        crate::Error::HclEval(e) if matches!(e.kind(), hcl::eval::ErrorKind::UndefinedVar(_)),
+    ));
+
+    // a plain `enabled` attribute is not a meta-argument
+    let hcl = "feature {\n  enabled = \"yes\"\n  x = 1\n}\ny = feature.enabled";
+    let body = crate::parse(hcl).unwrap();
+    let expected = "feature {\n  enabled = \"yes\"\n  x = 1\n}\ny = \"yes\"";
+    assert_eq!(body, hcl::parse(expected).unwrap());
+}
+
+#[test]
+fn test_self_parent_root() {
+    let hcl = r#"
+        name = "app"
+        svc "web" {
+            name = "web"
+            full = "${root.name}-${self.name}"
+            port = root.ports.web
+            inner {
+                name = "inner"
+                up = parent.name
+                own = self.name
+                top = root.svc.web.port
+            }
+        }
+        ports = { web = 8080 }
+        env {
+            for_each = ["dev"]
+            label = "${root.name}-${each.key}"
+            up = parent.name
+        }
+        "#;
+    let expected = r#"
+        name = "app"
+        svc "web" {
+            name = "web"
+            full = "app-web"
+            port = 8080
+            inner {
+                name = "inner"
+                up = "web"
+                own = "inner"
+                top = 8080
+            }
+        }
+        ports = { "web" = 8080 }
+        env "dev" {
+            label = "app-dev"
+            up = "app"
+        }
+        "#;
+    assert_eq!(crate::parse(hcl).unwrap(), hcl::parse(expected).unwrap());
+
+    // attributes with the names of the built-in objects take precedence, even when defined later
+    let hcl = r"
+        x = self.a
+        a = 7
+        self = { a = 1 }
+        y = root.a
+        root = { a = 2 }
+        blk {
+            z = parent.b
+            parent = { b = 3 }
+        }
+        ";
+    let expected = r#"
+        x = 1
+        a = 7
+        self = { "a" = 1 }
+        y = 2
+        root = { "a" = 2 }
+        blk {
+            z = 3
+            parent = { "b" = 3 }
+        }
+        "#;
+    assert_eq!(crate::parse(hcl).unwrap(), hcl::parse(expected).unwrap());
+
+    let err = crate::parse("x = parent.y").unwrap_err();
+    assert!(matches!(
+        err.inner(),
+        crate::Error::HclEval(e) if matches!(e.kind(), hcl::eval::ErrorKind::UndefinedVar(_)),
+    ));
+}
+
+#[test]
+fn test_cached_contexts() {
+    // the contexts of the scopes are kept across attributes, so they must see every update
+    let hcl = "
+        a = 1
+        early = root.a
+        first = blk.x
+        blk {
+            x = root.a + 1
+            y = self.x + root.a
+            inner {
+                z = parent.y + 1
+            }
+            w = inner.z + self.y
+        }
+        b = blk.w + root.blk.x + first
+        svc {
+            for_each = { p = 1, q = 2 }
+            v = each.value + root.a
+            u = self.v * 10
+        }
+        c = svc.q.u + svc.p.v
+        late = root.svc.q.u + length(root.svc)
+        ";
+    let expected = r#"
+        a = 1
+        early = 1
+        first = 2
+        blk {
+            x = 2
+            y = 3
+            inner {
+                z = 4
+            }
+            w = 7
+        }
+        b = 11
+        svc "p" {
+            v = 2
+            u = 20
+        }
+        svc "q" {
+            v = 3
+            u = 30
+        }
+        c = 32
+        late = 32
+        "#;
+    let mut en = crate::Engine::new();
+    assert_eq!(en.parse(hcl).unwrap(), hcl::parse(expected).unwrap());
+
+    // and the changes made between parses
+    en.clean_up().set_var("n", 1).unwrap();
+    assert_eq!(en.parse("m = n").unwrap(), hcl::parse("m = 1").unwrap());
+    en.clean_up().set_var("n", 2).unwrap();
+    assert_eq!(en.parse("m = n").unwrap(), hcl::parse("m = 2").unwrap());
+}
+
+#[test]
+fn test_parse_dir() {
+    let dir = std::env::temp_dir().join(format!("ensan-test-parse-dir-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.hcl"), "app {\n  port = root.port\n}").unwrap();
+    std::fs::write(dir.join("vars.hcl"), "port = 8080").unwrap();
+    std::fs::write(dir.join("notes.txt"), "not hcl").unwrap();
+    let mut en = crate::Engine::new();
+    let body = en.parse_dir(&dir).unwrap();
+    let expected = "app {\n  port = 8080\n}\nport = 8080";
+    assert_eq!(body, hcl::parse(expected).unwrap());
+
+    std::fs::write(dir.join("vars.hcl"), "port = 8080\nhost = nope").unwrap();
+    en.clean_up();
+    let err = en.parse_dir(&dir).unwrap_err();
+    let loc = err.location().unwrap();
+    assert_eq!(loc.file, Some(dir.join("vars.hcl").display().to_string()));
+    assert_eq!((loc.line, loc.column, loc.path.as_str()), (2, 8, "host"));
+
+    let err = en.parse_file(dir.join("missing.hcl")).unwrap_err();
+    assert!(matches!(err, crate::Error::Io { .. }));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_include() {
+    let dir = std::env::temp_dir().join(format!("ensan-test-include-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("common")).unwrap();
+    let main = dir.join("main.hcl");
+    std::fs::write(
+        &main,
+        "include \"common/net.hcl\" {}\nurl = \"${host}:${port}\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("common/net.hcl"),
+        "host = \"localhost\"\nsvc {\n  import \"ports.hcl\" {}\n}\nport = svc.port",
+    )
+    .unwrap();
+    std::fs::write(dir.join("common/ports.hcl"), "port = 8080").unwrap();
+    let engine = || {
+        let mut en = crate::Engine::new();
+        en.include_dir = Some(dir.clone());
+        en
+    };
+    let body = engine().parse_file(&main).unwrap();
+    let expected = r#"
+        host = "localhost"
+        svc {
+            port = 8080
+        }
+        port = 8080
+        url = "localhost:8080"
+        "#;
+    assert_eq!(body, hcl::parse(expected).unwrap());
+
+    // errors are located in the included file
+    std::fs::write(dir.join("common/ports.hcl"), "port = nope").unwrap();
+    let err = engine().parse_file(&main).unwrap_err();
+    let loc = err.location().unwrap();
+    assert!(loc.file.as_deref().unwrap().ends_with("ports.hcl"));
+    assert_eq!(
+        (loc.line, loc.column, loc.path.as_str()),
+        (1, 8, "svc > port")
+    );
+
+    std::fs::write(dir.join("common/ports.hcl"), "include \"../main.hcl\" {}").unwrap();
+    let err = engine().parse_file(&main).unwrap_err();
+    let crate::Error::IncludeCycle(files) = err.inner() else {
+        panic!("{err}")
+    };
+    assert_eq!(files.len(), 4);
+    assert_eq!(err.location().unwrap().path, r#"include "../main.hcl""#);
+
+    // includes are disabled by default, and limited to `include_dir`
+    let err = crate::Engine::new().parse_file(&main).unwrap_err();
+    assert!(matches!(err.inner(), crate::Error::Include { .. }), "{err}");
+    let outside =
+        std::env::temp_dir().join(format!("ensan-test-outside-{}.hcl", std::process::id()));
+    std::fs::write(&outside, "secret = 1").unwrap();
+    let hcl = format!("include \"{}\" {{}}", outside.display());
+    let err = engine().parse(&hcl).unwrap_err();
+    assert!(err.to_string().contains("is outside of"), "{err}");
+    std::fs::remove_file(&outside).unwrap();
+    // the body of the block would be lost
+    std::fs::write(&main, "include \"common/ports.hcl\" {\n  a = 1\n}").unwrap();
+    let err = engine().parse_file(&main).unwrap_err();
+    assert!(matches!(err.inner(), crate::Error::Include { .. }), "{err}");
+    assert_eq!(err.location().unwrap().line, 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_locals() {
+    let hcl = r#"
+        locals {
+            env = "prod"
+        }
+        svc "web" {
+            name = "web-${local.env}"
+            tags {
+                region = local.region
+            }
+        }
+        locals {
+            region = "eu-${local.env}"
+        }
+        "#;
+    let expected = r#"
+        locals {
+            env = "prod"
+        }
+        svc "web" {
+            name = "web-prod"
+            tags {
+                region = "eu-prod"
+            }
+        }
+        locals {
+            region = "eu-prod"
+        }
+        "#;
+    let mut en = crate::Engine::new();
+    assert_eq!(en.parse(hcl).unwrap(), hcl::parse(expected).unwrap());
+    en.clean_up();
+    en.strip_locals = true;
+    let body = en.parse(hcl).unwrap();
+    assert_eq!(body.blocks().count(), 1);
+
+    let mut en = crate::Engine::new();
+    en.strict = true;
+    let err = en
+        .parse("locals {\n  a = 1\n}\nlocals {\n  a = 2\n}")
+        .unwrap_err();
+    assert!(matches!(err.inner(), crate::Error::Redefined { path, .. } if path == "local.a"));
+}
+
+#[test]
+fn test_variables() {
+    let hcl = r#"
+        svc "web" {
+            port = var.port
+            hosts = [for h in var.hosts : "${h}:${var.port}"]
+        }
+        variable "port" {
+            type = number
+            default = 80
+        }
+        variable "hosts" {
+            type = list(string)
+            default = ["a", "b"]
+            description = "hosts to serve"
+        }
+        "#;
+    let mut en = crate::Engine::new();
+    let body = en.parse(hcl).unwrap();
+    let expected = "port = 80\nhosts = [\"a:80\", \"b:80\"]";
+    assert_eq!(
+        body.blocks().next().unwrap().body,
+        hcl::parse(expected).unwrap()
+    );
+
+    let dir = std::env::temp_dir().join(format!("ensan-test-variables-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("prod.hcl"), "port = \"443\"\nhosts = [\"c\"]").unwrap();
+    let mut en = crate::Engine::new();
+    en.set_vars_from_file(dir.join("prod.hcl")).unwrap();
+    let body = en.parse(hcl).unwrap();
+    let expected = "port = 443\nhosts = [\"c:443\"]";
+    assert_eq!(
+        body.blocks().next().unwrap().body,
+        hcl::parse(expected).unwrap()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    en.set_var("var.port", "many").unwrap();
+    en.clean_up();
+    let err = en.parse(hcl).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"6:9 (variable "port"): variable `port`: expected number, got `"many"`"#,
+    );
+    let mut en = crate::Engine::new();
+    let err = en.parse("variable \"x\" {\n  type = strin\n}").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"2:10 (variable "x" > type): variable `x`: unknown type `strin`"#,
+    );
+    let err = en.parse("variable \"x\" {}\ny = var.x").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"1:1 (variable "x"): variable `x`: no value was set and there is no default"#,
+    );
+}
+
+#[test]
+fn test_validation() {
+    let hcl = r#"
+        variable "port" {
+            type = number
+            default = 80
+            validation {
+                condition = var.port > 0 && var.port < 65536
+                error_message = "port must be between 1 and 65535, got ${var.port}"
+            }
+        }
+        svc {
+            for_each = { web = 2, api = 1 }
+            replicas = each.value
+            validation {
+                condition = replicas <= 2
+                error_message = "${each.key} has too many replicas"
+            }
+        }
+        "#;
+    let expected = r#"
+        variable "port" {
+            type = number
+            default = 80
+        }
+        svc "web" {
+            replicas = 2
+        }
+        svc "api" {
+            replicas = 1
+        }
+        "#;
+    let mut en = crate::Engine::new();
+    assert_eq!(en.parse(hcl).unwrap(), hcl::parse(expected).unwrap());
+
+    en.set_var("var.port", 0).unwrap();
+    en.clean_up();
+    let err = en.parse(hcl).unwrap_err();
+    assert!(matches!(err.inner(), crate::Error::Validation { .. }));
+    assert_eq!(
+        err.to_string(),
+        r#"6:29 (variable "port" > validation > condition): port must be between 1 and 65535, got 0"#,
+    );
+
+    let mut en = crate::Engine::new();
+    en.collect_errors = true;
+    let err = en
+        .parse(hcl.replace("{ web = 2, api = 1 }", "{ web = 3, api = 4 }"))
+        .unwrap_err();
+    let crate::Error::Multiple { errors, .. } = err else {
+        panic!("expected multiple errors");
+    };
+    let messages = errors
+        .iter()
+        .map(|e| e.inner().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        ["web has too many replicas", "api has too many replicas"]
+    );
+
+    let mut en = crate::Engine::new();
+    let err = en
+        .parse("x = 1\nvalidation {\n  condition = x\n}")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "3:15 (validation > condition): Hcl Eval error: unexpected value `1`, expected a bool",
+    );
+}
@@ -0,0 +1,237 @@
+//! # Type constraints
+//!
+//! The `type` of `variable` blocks, e.g. `list(string)`, used by [`crate::Engine`] to check and
+//! convert the values of the variables.
+use hcl::{eval::Evaluate, Expression, ObjectKey, Value};
+use itertools::Itertools;
+
+/// A type constraint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Any,
+    String,
+    Number,
+    Bool,
+    List(Box<Self>),
+    /// a list without duplicates
+    Set(Box<Self>),
+    Map(Box<Self>),
+    Tuple(Vec<Self>),
+    Object(Vec<(String, Self)>),
+}
+
+impl Type {
+    /// Parse a type constraint written as an expression, e.g. `map(number)` or
+    /// `object({ name = string, ports = list(number) })`.
+    pub fn parse(expr: &Expression) -> Result<Self, String> {
+        let invalid = || format!("invalid type constraint `{expr}`");
+        match expr {
+            Expression::Variable(var) => match var.as_str() {
+                "any" => Ok(Self::Any),
+                "string" => Ok(Self::String),
+                "number" => Ok(Self::Number),
+                "bool" => Ok(Self::Bool),
+                name => Err(format!("unknown type `{name}`")),
+            },
+            Expression::FuncCall(call) => {
+                let [arg] = &call.args[..] else {
+                    return Err(invalid());
+                };
+                match (call.name.name.as_str(), arg) {
+                    ("list", _) => Ok(Self::List(Box::new(Self::parse(arg)?))),
+                    ("set", _) => Ok(Self::Set(Box::new(Self::parse(arg)?))),
+                    ("map", _) => Ok(Self::Map(Box::new(Self::parse(arg)?))),
+                    ("tuple", Expression::Array(items)) => {
+                        Ok(Self::Tuple(items.iter().map(Self::parse).try_collect()?))
+                    }
+                    ("object", Expression::Object(attrs)) => (attrs.iter())
+                        .map(|(key, ty)| {
+                            let key = match key {
+                                ObjectKey::Identifier(id) => id.to_string(),
+                                ObjectKey::Expression(Expression::String(s)) => s.clone(),
+                                _ => return Err(invalid()),
+                            };
+                            Ok((key, Self::parse(ty)?))
+                        })
+                        .try_collect()
+                        .map(Self::Object),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Check that `value` conforms to the type, converting it where possible.
+    ///
+    /// Like in Terraform, numbers and bools are converted to and from strings, and the attributes
+    /// of objects that are not in the type are dropped. Strings given for collections are parsed
+    /// as hcl expressions, e.g. `["a", "b"]` from an environment variable. `null` conforms to every
+    /// type.
+    pub fn convert(&self, value: Value) -> Result<Value, String> {
+        let mismatch = |value: &Value| match value {
+            Value::Array(_) => format!("expected {self}, got a list"),
+            Value::Object(_) => format!("expected {self}, got an object"),
+            _ => format!("expected {self}, got `{value}`"),
+        };
+        match (self, value) {
+            (Self::Any, value)
+            | (_, value @ Value::Null)
+            | (Self::String, value @ Value::String(_)) => Ok(value),
+            (Self::String, Value::Number(n)) => Ok(Value::String(n.to_string())),
+            (Self::String, Value::Bool(b)) => Ok(Value::String(b.to_string())),
+            (Self::Number, value @ Value::Number(_)) | (Self::Bool, value @ Value::Bool(_)) => {
+                Ok(value)
+            }
+            (Self::Number, Value::String(s)) => (s.trim().parse::<i64>().ok())
+                .map(hcl::Number::from)
+                .or_else(|| s.trim().parse().ok().and_then(hcl::Number::from_f64))
+                .map(Value::Number)
+                .ok_or_else(|| mismatch(&Value::String(s))),
+            (Self::Bool, Value::String(s)) => match s.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => Err(mismatch(&Value::String(s))),
+            },
+            (
+                Self::List(_) | Self::Set(_) | Self::Map(_) | Self::Tuple(_) | Self::Object(_),
+                Value::String(s),
+            ) => match parse_value(&s) {
+                Some(value @ (Value::Array(_) | Value::Object(_))) => self.convert(value),
+                _ => Err(mismatch(&Value::String(s))),
+            },
+            (Self::List(ty), Value::Array(items)) => items
+                .into_iter()
+                .map(|v| ty.convert(v))
+                .try_collect()
+                .map(Value::Array),
+            (Self::Set(ty), Value::Array(items)) => (items.into_iter())
+                .map(|v| ty.convert(v))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|items| {
+                    Value::Array(items.into_iter().unique_by(ToString::to_string).collect())
+                }),
+            (Self::Tuple(types), Value::Array(items)) if types.len() == items.len() => {
+                (types.iter().zip(items))
+                    .map(|(ty, v)| ty.convert(v))
+                    .try_collect()
+                    .map(Value::Array)
+            }
+            (Self::Map(ty), Value::Object(attrs)) => (attrs.into_iter())
+                .map(|(k, v)| Ok((k, ty.convert(v)?)))
+                .try_collect()
+                .map(Value::Object),
+            (Self::Object(types), Value::Object(mut attrs)) => (types.iter())
+                .map(|(k, ty)| {
+                    let v =
+                        (attrs.swap_remove(k)).ok_or_else(|| format!("missing attribute `{k}`"))?;
+                    let v = ty.convert(v).map_err(|e| format!("attribute `{k}`: {e}"))?;
+                    Ok((k.clone(), v))
+                })
+                .try_collect()
+                .map(Value::Object),
+            (_, value) => Err(mismatch(&value)),
+        }
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Any => f.write_str("any"),
+            Self::String => f.write_str("string"),
+            Self::Number => f.write_str("number"),
+            Self::Bool => f.write_str("bool"),
+            Self::List(ty) => write!(f, "list({ty})"),
+            Self::Set(ty) => write!(f, "set({ty})"),
+            Self::Map(ty) => write!(f, "map({ty})"),
+            Self::Tuple(types) => write!(f, "tuple([{}])", types.iter().join(", ")),
+            Self::Object(attrs) => write!(
+                f,
+                "object({{ {} }})",
+                attrs.iter().map(|(k, ty)| format!("{k} = {ty}")).join(", ")
+            ),
+        }
+    }
+}
+
+/// Parse and evaluate `s` as an hcl expression without any variables.
+fn parse_value(s: &str) -> Option<Value> {
+    let body = hcl::parse(&format!("value = {s}")).ok()?;
+    let attr = body.attributes().next()?;
+    attr.expr.evaluate(&hcl::eval::Context::new()).ok()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn ty(s: &str) -> Type {
+        let body = hcl::parse(&format!("type = {s}")).unwrap();
+        Type::parse(&body.attributes().next().unwrap().expr).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ty("string"), Type::String);
+        assert_eq!(ty("list(number)"), Type::List(Box::new(Type::Number)));
+        assert_eq!(
+            ty("object({ name = string, tags = map(any) })"),
+            Type::Object(vec![
+                ("name".into(), Type::String),
+                ("tags".into(), Type::Map(Box::new(Type::Any))),
+            ]),
+        );
+        assert_eq!(
+            ty("tuple([bool, set(string)])").to_string(),
+            "tuple([bool, set(string)])"
+        );
+        let body = hcl::parse("a = strin\nb = list(1)\nc = map(string, number)").unwrap();
+        let errs = (body.attributes())
+            .map(|attr| Type::parse(&attr.expr).unwrap_err())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errs,
+            [
+                "unknown type `strin`",
+                "invalid type constraint `1`",
+                "invalid type constraint `map(string, number)`",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_convert() {
+        let s = |s: &str| Value::String(s.into());
+        assert_eq!(ty("number").convert(s("8080")), Ok(8080.into()));
+        assert_eq!(ty("bool").convert(s("true")), Ok(true.into()));
+        assert_eq!(ty("string").convert(1.5.into()), Ok(s("1.5")));
+        assert_eq!(ty("any").convert(s("x")), Ok(s("x")));
+        assert_eq!(ty("number").convert(Value::Null), Ok(Value::Null));
+        assert_eq!(
+            ty("list(number)").convert(s(r#"[1, "2"]"#)),
+            Ok(Value::from(vec![1, 2])),
+        );
+        assert_eq!(
+            ty("set(string)").convert(Value::from(vec!["a", "b", "a"])),
+            Ok(Value::from(vec!["a", "b"])),
+        );
+        assert_eq!(
+            ty("object({ a = number })").convert(parse_value("{ a = 1, b = 2 }").unwrap()),
+            Ok(parse_value("{ a = 1 }").unwrap()),
+        );
+        assert_eq!(
+            ty("number").convert(s("many")),
+            Err("expected number, got `\"many\"`".into()),
+        );
+        assert_eq!(
+            ty("object({ a = number, b = bool })").convert(parse_value("{ a = 1 }").unwrap()),
+            Err("missing attribute `b`".into()),
+        );
+        assert_eq!(
+            ty("map(bool)").convert(Value::from(vec![true])),
+            Err("expected map(bool), got a list".into()),
+        );
+    }
+}